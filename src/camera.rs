@@ -58,6 +58,63 @@ impl Camera {
 
         Ray::new(self.origin.into(), dir.into())
     }
+
+    /// pixel seen towards `point`, None if the point is out of the image
+    pub fn project(&self, point: DVec3, (x_res, y_res): (usize, usize)) -> Option<(usize, usize)> {
+        let (normal, distance) = self.sensor_plane();
+        let dir = point - self.origin;
+        let cos = normal.dot(dir);
+        if cos <= 0.0 {
+            return None;
+        }
+
+        // position on the sensor, solved in the basis of its sides
+        let on_sensor = self.origin + dir * distance / cos - self.pixel_lower_left;
+        let (xx, xy, yy) = (
+            self.x_vec.length_squared(),
+            self.x_vec.dot(self.y_vec),
+            self.y_vec.length_squared(),
+        );
+        let (px, py) = (on_sensor.dot(self.x_vec), on_sensor.dot(self.y_vec));
+        let det = xx * yy - xy * xy;
+        let dx = (px * yy - py * xy) / det;
+        let dy = (py * xx - px * xy) / det;
+
+        // pixels are centered on their coordinates, see `ray`
+        let x = (dx * x_res as f64 + 0.5).floor();
+        let y = (dy * y_res as f64 + 0.5).floor();
+        if x < 0.0 || y < 0.0 || x >= x_res as f64 || y >= y_res as f64 {
+            return None;
+        }
+
+        Some((x as usize, y as usize))
+    }
+
+    /// solid angle density of `ray` returning `dir` for a random pixel, for directions inside
+    /// the image
+    pub fn pdf(&self, dir: DVec3) -> f64 {
+        let (normal, distance) = self.sensor_plane();
+        let cos = normal.dot(dir.normalize());
+        if cos <= 0.0 {
+            return 0.0;
+        }
+
+        // the sensor area seen through a solid angle grows with the squared distance and
+        // shrinks with the cosine of the sensor
+        let area = self.x_vec.cross(self.y_vec).length();
+        distance * distance / (area * cos * cos * cos)
+    }
+
+    /// normal of the sensor facing away from the origin, and the distance to it
+    fn sensor_plane(&self) -> (DVec3, f64) {
+        let normal = self.x_vec.cross(self.y_vec).normalize();
+        let distance = normal.dot(self.dir * self.sensor_distance);
+        if distance < 0.0 {
+            (-normal, -distance)
+        } else {
+            (normal, distance)
+        }
+    }
 }
 
 #[test]
@@ -96,3 +153,50 @@ fn camera() {
     assert!((camera.ray((2000, 2000), (2000, 2000)).dir.y - p.y).abs() <= 10e-4);
     assert!((camera.ray((2000, 2000), (2000, 2000)).dir.z - p.z).abs() <= 10e-4);
 }
+
+#[test]
+fn points_project_to_the_pixel_of_their_rays() {
+    let camera = Camera::new(
+        DVec3::new(1.0, 2.0, 3.0),
+        DVec3::new(0.3, -0.4, -1.0).normalize(),
+        DVec3::Y,
+        70.0f64.to_radians(),
+        50.0f64.to_radians(),
+        1.0,
+    );
+    let resolution = (64, 48);
+
+    for pixel in [(0, 0), (10, 20), (63, 47), (32, 24)] {
+        let ray = camera.ray(pixel, resolution);
+        assert_eq!(
+            camera.project(ray.origin + 5.0 * ray.dir, resolution),
+            Some(pixel)
+        );
+    }
+    assert_eq!(camera.project(camera.origin - camera.dir, resolution), None);
+}
+
+#[test]
+fn pdf_integrates_to_one_over_the_image() {
+    let camera = Camera::new(
+        DVec3::ZERO,
+        DVec3::new(0.2, 0.1, 1.0).normalize(),
+        DVec3::Y,
+        60.0f64.to_radians(),
+        40.0f64.to_radians(),
+        1.0,
+    );
+
+    // uniform directions weighted by the density, inside the image only
+    let n = 200_000;
+    let mut integral = 0.0;
+    for _ in 0..n {
+        let dir = crate::sampling::uniform_sphere();
+        if camera.project(dir, (100, 100)).is_some() {
+            integral += camera.pdf(dir) * 4.0 * std::f64::consts::PI;
+        }
+    }
+    integral /= n as f64;
+
+    assert!((integral - 1.0).abs() < 0.05, "{integral}");
+}
@@ -0,0 +1,92 @@
+use glam::DVec3;
+
+/// Linear RGB color, also used for radiance and reflectance values
+pub type Color = DVec3;
+
+/// perceived brightness of a linear sRGB color (Rec. 709 weights)
+pub fn luminance(color: Color) -> f64 {
+    color.dot(DVec3::new(0.2126, 0.7152, 0.0722))
+}
+
+/// `color` scaled to a luminance of 1, so it only carries the hue. Black stays black
+pub fn normalized(color: Color) -> Color {
+    let luminance = luminance(color);
+    if luminance > 0.0 {
+        color / luminance
+    } else {
+        Color::ZERO
+    }
+}
+
+/// color of the light emitted by a black body at `kelvin`, with a luminance of 1. Colors out of
+/// the sRGB gamut, like the deep reds of low temperatures, are clamped
+pub fn blackbody(kelvin: f64) -> Color {
+    // Planck's law, in arbitrary units
+    let planck = |nm: f64| {
+        let wavelength = nm * 1e-9;
+        1.0 / (wavelength.powi(5) * ((1.4387769e-2 / (wavelength * kelvin)).exp() - 1.0))
+    };
+
+    let xyz = (380..=780)
+        .step_by(5)
+        .map(|nm| {
+            let nm = nm as f64;
+            planck(nm) * cie_xyz(nm)
+        })
+        .fold(DVec3::ZERO, |sum, xyz| sum + xyz);
+
+    normalized(xyz_to_rgb(xyz).max(Color::ZERO))
+}
+
+/// linear sRGB color of CIE XYZ tristimulus values. Colors out of the sRGB gamut get negative
+/// components
+pub fn xyz_to_rgb(xyz: DVec3) -> Color {
+    Color::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+/// CIE 1931 color matching functions at the wavelength `nm`, using the multi-lobe fit of
+/// Wyman et al., "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+fn cie_xyz(nm: f64) -> DVec3 {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let t = (nm - mean) / if nm < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+
+    DVec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::color::{blackbody, luminance, normalized, Color};
+
+    #[test]
+    fn normalized_colors_have_unit_luminance() {
+        assert!((luminance(normalized(Color::new(0.5, 2.0, 3.0))) - 1.0).abs() < 1e-9);
+        assert_eq!(normalized(Color::ZERO), Color::ZERO);
+    }
+
+    #[test]
+    fn blackbody_goes_from_red_to_blue() {
+        let candle = blackbody(1900.0);
+        let daylight = blackbody(6500.0);
+        let sky = blackbody(12000.0);
+
+        for color in [candle, daylight, sky] {
+            assert!((luminance(color) - 1.0).abs() < 1e-9);
+        }
+
+        assert!(candle.x > candle.y && candle.y > candle.z);
+        assert!(sky.z > sky.y && sky.y > sky.x);
+        // close to the white point of sRGB
+        assert!(daylight.abs_diff_eq(Color::ONE, 0.1), "{daylight}");
+    }
+}
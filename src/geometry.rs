@@ -1,6 +1,8 @@
 use std::fmt::Debug;
 
-use glam::DVec3;
+use glam::{DVec2, DVec3};
+
+use crate::material::Material;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Ray {
@@ -8,6 +10,9 @@ pub struct Ray {
     pub dir: DVec3,
     /// direction reciprocal
     pub dir_recip: DVec3,
+    /// distances along the ray objects can be hit between, excluded
+    pub t_min: f64,
+    pub t_max: f64,
 }
 
 /// Axis-aligned bounding box defined by min and max points
@@ -17,11 +22,83 @@ pub struct AABBox {
     pub max: DVec3,
 }
 
+/// Error allowed on computed hit points, relative to the magnitude of their coordinates. Rays
+/// leaving a surface start that far from it so they don't hit it again, far above the rounding
+/// errors of f64
+pub const RAY_EPSILON: f64 = 1e-9;
+
+/// Ray-object intersection
+#[derive(Clone, Copy, Debug)]
+pub struct Hit<'a> {
+    /// distance along the ray
+    pub t: f64,
+    pub point: DVec3,
+    /// normal the surface is shaded with, the geometric one unless it is interpolated or
+    /// perturbed
+    pub normal: DVec3,
+    /// normal of the surface itself, pointing out of closed objects
+    pub geometric_normal: DVec3,
+    /// if the ray hit the side the geometric normal points to, going into closed objects
+    pub front_face: bool,
+    /// texture coordinates at the intersection point
+    pub uv: DVec2,
+    /// derivatives of the point with respect to u and v, spanning the tangent plane
+    pub dpdu: DVec3,
+    pub dpdv: DVec3,
+    /// weights of the vertices of the triangle hit, zero for other surfaces
+    pub barycentric: DVec3,
+    pub material: &'a dyn Material,
+    /// object the ray hit
+    pub object: &'a dyn Intersect,
+    /// index of the object hit among those the octree was built from, zero for objects
+    /// intersected on their own
+    pub primitive: usize,
+}
+
+/// Point sampled on a surface as seen from another point
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceSample {
+    pub point: DVec3,
+    pub normal: DVec3,
+    /// solid angle density of the direction to the point, as seen from the other point
+    pub pdf: f64,
+}
+
 pub trait Intersect: Sync + Debug {
-    /// if it intersects, return the normal at the intersection point
-    fn intersect(&self, ray: Ray) -> Option<Ray>;
+    /// if it intersects within the interval of the ray, return the nearest hit
+    fn intersect(&self, ray: Ray) -> Option<Hit<'_>>;
+
+    /// if anything is hit within the interval of the ray, stopping at the first hit found
+    fn occluded(&self, ray: Ray) -> bool {
+        self.intersect(ray).is_some()
+    }
 
     fn bounds(&self) -> AABBox;
+
+    /// material of the whole surface, for objects that can be light sources
+    fn material(&self) -> Option<&dyn Material> {
+        None
+    }
+
+    /// random point of the surface visible from `origin`. None if it can't be sampled
+    fn sample(&self, _origin: DVec3) -> Option<SurfaceSample> {
+        None
+    }
+
+    /// solid angle density of `sample` returning `point` of the surface, seen from `origin`
+    fn sample_pdf(&self, _origin: DVec3, _point: DVec3) -> f64 {
+        0.0
+    }
+
+    /// surface area, for objects that can be light sources
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    /// uniformly distributed point of the surface, with its normal. None if it can't be sampled
+    fn sample_point(&self) -> Option<(DVec3, DVec3)> {
+        None
+    }
 }
 
 impl Ray {
@@ -34,6 +111,8 @@ impl Ray {
             origin: (origin_x, origin_y, origin_z).into(),
             dir,
             dir_recip: dir.recip(),
+            t_min: 0.0,
+            t_max: f64::INFINITY,
         }
     }
 
@@ -48,17 +127,93 @@ impl Ray {
             origin: (origin_x, origin_y, origin_z).into(),
             dir,
             dir_recip: dir.recip(),
+            t_min: 0.0,
+            t_max: f64::INFINITY,
         }
     }
 
-    pub fn reflect(&self, normal: Ray) -> Ray {
-        let dir = (2.0 * normal.dir.dot(-self.dir) * normal.dir + self.dir).normalize();
+    /// the ray only hitting objects between `t_min` and `t_max`
+    pub fn with_interval(self, t_min: f64, t_max: f64) -> Self {
         Ray {
-            origin: normal.origin,
-            dir,
-            dir_recip: dir.recip(),
+            t_min,
+            t_max,
+            ..self
         }
     }
+
+    /// point at distance `t` along the ray
+    pub fn at(&self, t: f64) -> DVec3 {
+        self.origin + t * self.dir
+    }
+
+    /// if `t` is inside the interval of the ray
+    pub fn contains(&self, t: f64) -> bool {
+        t > self.t_min && t < self.t_max
+    }
+}
+
+/// origin of rays leaving the surface at `point` towards `dir`, moved off the surface to the
+/// side `dir` goes to, by more than the error on the point
+pub fn offset_origin(point: DVec3, normal: DVec3, dir: DVec3) -> DVec3 {
+    let offset = RAY_EPSILON * (1.0 + point.abs().max_element());
+    if dir.dot(normal) >= 0.0 {
+        point + offset * normal
+    } else {
+        point - offset * normal
+    }
+}
+
+/// reflect `dir` around `normal`
+pub fn reflect(dir: DVec3, normal: DVec3) -> DVec3 {
+    (2.0 * normal.dot(-dir) * normal + dir).normalize()
+}
+
+/// refract `dir` through a surface with `normal` facing it, where `eta` is the ratio of the
+/// refraction indices (incident over transmitted). Returns None on total internal reflection
+pub fn refract(dir: DVec3, normal: DVec3, eta: f64) -> Option<DVec3> {
+    let cos_i = normal.dot(-dir);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((eta * dir + (eta * cos_i - cos_t) * normal).normalize())
+}
+
+/// `normal` flipped, if needed, to face against `dir`
+pub fn face_forward(normal: DVec3, dir: DVec3) -> DVec3 {
+    if normal.dot(dir) > 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+impl Hit<'_> {
+    /// ray leaving the hit point towards `dir`, offset to the side of the surface it goes to
+    pub fn spawn_ray(&self, dir: DVec3) -> Ray {
+        let origin = offset_origin(self.point, self.geometric_normal, dir);
+        Ray::new(origin.into(), dir.into())
+    }
+
+    /// ray leaving the hit point towards `dir` that stops just before `distance`, for
+    /// visibility tests against points at that distance
+    pub fn spawn_shadow_ray(&self, dir: DVec3, distance: f64) -> Ray {
+        if distance.is_infinite() {
+            return self.spawn_ray(dir);
+        }
+
+        // aimed at the point from the offset origin: keeping `dir` would shift the end of the
+        // ray sideways, far along it where it grazes the surface at that point
+        let target = self.point + distance * dir;
+        let origin = offset_origin(self.point, self.geometric_normal, dir);
+        let to_target = target - origin;
+        let length = to_target.length();
+        Ray::new(origin.into(), (to_target / length).into())
+            .with_interval(0.0, length * (1.0 - RAY_EPSILON))
+    }
 }
 
 impl AABBox {
@@ -84,6 +239,14 @@ impl AABBox {
         ]
     }
 
+    /// smallest box containing both boxes
+    pub fn union(&self, other: &Self) -> AABBox {
+        AABBox {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
     pub fn intersect_other(&self, other: &Self) -> bool {
         fn interval_intersect(a: (f64, f64), b: (f64, f64)) -> bool {
             !(b.0 > a.1 || a.0 > b.1)
@@ -93,14 +256,18 @@ impl AABBox {
             && interval_intersect((self.min.y, self.max.y), (other.min.y, other.max.y))
             && interval_intersect((self.min.z, self.max.z), (other.min.z, other.max.z))
     }
-}
 
-impl Intersect for AABBox {
-    fn intersect(&self, ray: Ray) -> Option<Ray> {
+    pub fn intersect(&self, ray: Ray) -> bool {
+        self.entry(ray).is_some()
+    }
+
+    /// distance along the ray where it enters the box, within the interval of the ray. None if
+    /// it misses the box in that interval
+    pub fn entry(&self, ray: Ray) -> Option<f64> {
         // slab method
 
-        let mut tmin = f64::NEG_INFINITY;
-        let mut tmax = f64::INFINITY;
+        let mut tmin = ray.t_min;
+        let mut tmax = ray.t_max;
 
         if ray.dir.x != 0.0 {
             let tx1 = (self.min.x - ray.origin.x) * ray.dir_recip.x;
@@ -108,6 +275,8 @@ impl Intersect for AABBox {
 
             tmin = tmin.max(tx1.min(tx2));
             tmax = tmax.min(tx1.max(tx2));
+        } else if ray.origin.x < self.min.x || ray.origin.x > self.max.x {
+            return None;
         }
 
         if ray.dir.y != 0.0 {
@@ -116,6 +285,8 @@ impl Intersect for AABBox {
 
             tmin = tmin.max(ty1.min(ty2));
             tmax = tmax.min(ty1.max(ty2));
+        } else if ray.origin.y < self.min.y || ray.origin.y > self.max.y {
+            return None;
         }
 
         if ray.dir.z != 0.0 {
@@ -124,6 +295,8 @@ impl Intersect for AABBox {
 
             tmin = tmin.max(tz1.min(tz2));
             tmax = tmax.min(tz1.max(tz2));
+        } else if ray.origin.z < self.min.z || ray.origin.z > self.max.z {
+            return None;
         }
 
         // TODO branchless is much slower. Why?
@@ -146,21 +319,53 @@ impl Intersect for AABBox {
         // tmin = tmin.max(tz1).min(tz2);
         // tmax = tmax.max(tz1).max(tz2);
 
-        if tmax >= tmin {
-            Some(ray) // TODO use real reflection?
-        } else {
-            None
-        }
-    }
-
-    fn bounds(&self) -> AABBox {
-        *self
+        (tmax >= tmin).then_some(tmin)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::geometry::AABBox;
+    use glam::DVec3;
+
+    use crate::geometry::{refract, AABBox};
+
+    #[test]
+    fn refraction_follows_snell_law() {
+        let dir = DVec3::new(1.0, -1.0, 0.0).normalize();
+
+        let refracted = refract(dir, DVec3::Y, 1.0 / 1.5).unwrap();
+
+        let sin_i = dir.x;
+        let sin_t = refracted.x;
+        assert!((sin_i - 1.5 * sin_t).abs() < 1e-9);
+        assert!(refracted.y < 0.0);
+    }
+
+    #[test]
+    fn refraction_at_normal_incidence_goes_straight() {
+        let refracted = refract(-DVec3::Y, DVec3::Y, 1.0 / 1.5).unwrap();
+
+        assert!(refracted.abs_diff_eq(-DVec3::Y, 1e-9));
+    }
+
+    #[test]
+    fn total_internal_reflection() {
+        // 60 degrees leaving glass to air is past the critical angle of ~41.8 degrees
+        let dir = DVec3::new(60.0f64.to_radians().sin(), -60.0f64.to_radians().cos(), 0.0);
+
+        assert!(refract(dir, DVec3::Y, 1.5).is_none());
+    }
+
+    #[test]
+    fn bbox_union_contains_both() {
+        let a = AABBox::new((0.0, 0.0, 0.0).into(), (5.0, 1.0, 5.0).into());
+        let b = AABBox::new((-4.0, 2.0, 1.0).into(), (1.0, 3.0, 2.0).into());
+
+        let union = a.union(&b);
+
+        assert_eq!(union.min, DVec3::new(-4.0, 0.0, 0.0));
+        assert_eq!(union.max, DVec3::new(5.0, 3.0, 5.0));
+    }
 
     #[test]
     fn bbox_intersect() {
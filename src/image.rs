@@ -0,0 +1,316 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::color::Color;
+
+/// Channel layout of the written PNG files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    /// RGB with an opaque alpha channel
+    Rgba,
+}
+
+pub fn save_to_png(
+    name: &str,
+    image: &[Color],
+    x_res: usize,
+    y_res: usize,
+    gamma_correction: f64,
+    format: PixelFormat,
+) {
+    let writer = BufWriter::new(File::create(name).unwrap());
+
+    let mut encoder = png::Encoder::new(writer, x_res as u32, y_res as u32);
+    encoder.set_color(match format {
+        PixelFormat::Rgb => png::ColorType::Rgb,
+        PixelFormat::Rgba => png::ColorType::Rgba,
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data_writer = encoder.write_header().unwrap();
+    data_writer
+        .write_image_data(&to_bytes(image, gamma_correction, format))
+        .unwrap();
+}
+
+/// gamma of the color values stored in 8 bit images
+pub const IMAGE_GAMMA: f64 = 2.2;
+
+/// Loads a PNG file as linear colors, row by row from the top, undoing the `gamma` the values
+/// are stored with. Returns the pixels, width and height, or None if the file can't be read as a
+/// PNG
+pub fn load_png(path: impl AsRef<Path>, gamma: f64) -> Option<(Vec<Color>, usize, usize)> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path).ok()?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info().ok()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).ok()?;
+
+    let channels = info.color_type.samples();
+    let linear = |value: u8| (value as f64 / 255.0).powf(gamma);
+
+    let pixels = data[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| match info.color_type {
+            png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                Color::splat(linear(pixel[0]))
+            }
+            _ => Color::new(linear(pixel[0]), linear(pixel[1]), linear(pixel[2])),
+        })
+        .collect();
+
+    Some((pixels, info.width as usize, info.height as usize))
+}
+
+/// Converts linear colors to gamma corrected 8 bit channels
+pub fn to_bytes(image: &[Color], gamma_correction: f64, format: PixelFormat) -> Vec<u8> {
+    let channel = |value: f64| (256.0 * value.max(0.0).powf(gamma_correction)) as u8;
+
+    image
+        .iter()
+        .flat_map(|color| {
+            let rgb = [channel(color.x), channel(color.y), channel(color.z)];
+            match format {
+                PixelFormat::Rgb => rgb.to_vec(),
+                PixelFormat::Rgba => vec![rgb[0], rgb[1], rgb[2], u8::MAX],
+            }
+        })
+        .collect()
+}
+
+/// Saves linear colors to a Radiance HDR (RGBE) file, with uncompressed scanlines
+pub fn save_hdr(name: &str, image: &[Color], x_res: usize, y_res: usize) {
+    let mut writer = BufWriter::new(File::create(name).unwrap());
+
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        y_res, x_res
+    )
+    .unwrap();
+    for color in image {
+        writer.write_all(&to_rgbe(*color)).unwrap();
+    }
+}
+
+/// Loads a Radiance HDR (RGBE) file, row by row from the top. Returns the pixels, width and
+/// height
+pub fn load_hdr(path: &str) -> (Vec<Color>, usize, usize) {
+    let mut reader = BufReader::new(File::open(path).unwrap());
+
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(
+        line.starts_with("#?"),
+        "{} is not a Radiance HDR file",
+        path
+    );
+
+    // header variables up to an empty line
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            assert_eq!(format, "32-bit_rle_rgbe", "unsupported HDR format");
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution[..] {
+        ["-Y", height, "+X", width] => (height.parse().unwrap(), width.parse().unwrap()),
+        _ => panic!("unsupported HDR orientation {:?}", line.trim()),
+    };
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut bytes = data.as_slice();
+    for _ in 0..height {
+        let (scanline, rest) = read_hdr_scanline(bytes, width);
+        pixels.extend(scanline.iter().map(|rgbe| from_rgbe(*rgbe)));
+        bytes = rest;
+    }
+
+    (pixels, width, height)
+}
+
+/// reads a scanline, run length encoded or flat. Returns it and the remaining bytes
+fn read_hdr_scanline(bytes: &[u8], width: usize) -> (Vec<[u8; 4]>, &[u8]) {
+    let run_length_encoded =
+        (8..0x8000).contains(&width) && bytes[0] == 2 && bytes[1] == 2 && bytes[2] < 0x80;
+
+    if !run_length_encoded {
+        let scanline = bytes[..4 * width]
+            .chunks_exact(4)
+            .map(|rgbe| [rgbe[0], rgbe[1], rgbe[2], rgbe[3]])
+            .collect();
+        return (scanline, &bytes[4 * width..]);
+    }
+
+    assert_eq!(
+        (bytes[2] as usize) << 8 | bytes[3] as usize,
+        width,
+        "HDR scanline width mismatch"
+    );
+
+    // each channel is encoded separately, in runs of a repeated byte or literal bytes
+    let mut scanline = vec![[0; 4]; width];
+    let mut pos = 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = bytes[pos] as usize;
+            pos += 1;
+            if count > 128 {
+                for pixel in &mut scanline[x..x + count - 128] {
+                    pixel[channel] = bytes[pos];
+                }
+                pos += 1;
+                x += count - 128;
+            } else {
+                for (pixel, byte) in scanline[x..x + count].iter_mut().zip(&bytes[pos..]) {
+                    pixel[channel] = *byte;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+
+    (scanline, &bytes[pos..])
+}
+
+/// shared exponent encoding of a color
+fn to_rgbe(color: Color) -> [u8; 4] {
+    let max = color.max_element();
+    if max < 1e-32 {
+        return [0; 4];
+    }
+
+    // max = mantissa * 2^exponent, with the mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    let channel = |value: f64| (value.max(0.0) * scale).min(255.0) as u8;
+
+    [
+        channel(color.x),
+        channel(color.y),
+        channel(color.z),
+        (exponent + 128) as u8,
+    ]
+}
+
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::ZERO;
+    }
+
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    Color::new(r as f64, g as f64, b as f64) * scale
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{
+        color::Color,
+        image::{load_hdr, load_png, save_hdr, save_to_png, to_bytes, PixelFormat, IMAGE_GAMMA},
+    };
+
+    #[test]
+    fn png_round_trip() {
+        let path = std::env::temp_dir().join("ray_tracer_png_round_trip.png");
+        let path = path.to_str().unwrap();
+        let image = [
+            Color::new(1.0, 0.0, 0.5),
+            Color::new(0.0, 0.25, 1.0),
+            Color::new(0.1, 0.2, 0.3),
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.75, 0.5, 0.25),
+        ];
+
+        save_to_png(path, &image, 3, 2, 1.0 / IMAGE_GAMMA, PixelFormat::Rgba);
+        let (loaded, width, height) = load_png(path, IMAGE_GAMMA).unwrap();
+
+        assert_eq!((width, height), (3, 2));
+        for (original, loaded) in image.iter().zip(loaded) {
+            assert!(original.abs_diff_eq(loaded, 0.02), "{original} {loaded}");
+        }
+    }
+
+    #[test]
+    fn rgb_channels_are_interleaved() {
+        let image = [Color::new(1.0, 0.0, 0.25), Color::new(0.0, 0.5, 2.0)];
+
+        assert_eq!(
+            to_bytes(&image, 1.0, PixelFormat::Rgb),
+            vec![255, 0, 64, 0, 128, 255]
+        );
+    }
+
+    #[test]
+    fn rgba_has_opaque_alpha() {
+        let image = [Color::new(0.0, 0.0, 0.0)];
+
+        assert_eq!(to_bytes(&image, 1.0, PixelFormat::Rgba), vec![0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let path = std::env::temp_dir().join("ray_tracer_hdr_round_trip.hdr");
+        let path = path.to_str().unwrap();
+        let image = [
+            Color::new(1.0, 0.5, 0.25),
+            Color::new(1000.0, 20.0, 0.0),
+            Color::ZERO,
+            Color::new(0.001, 0.002, 0.003),
+        ];
+
+        save_hdr(path, &image, 2, 2);
+        let (loaded, width, height) = load_hdr(path);
+
+        assert_eq!((width, height), (2, 2));
+        for (original, loaded) in image.iter().zip(loaded) {
+            // 8 bits of mantissa, relative to the largest channel
+            let tolerance = original.max_element() / 128.0;
+            assert!(
+                original.abs_diff_eq(loaded, tolerance),
+                "{original} {loaded}"
+            );
+        }
+    }
+
+    #[test]
+    fn hdr_run_length_encoded_scanlines() {
+        let path = std::env::temp_dir().join("ray_tracer_hdr_run_length_encoded.hdr");
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        data.extend([2, 2, 0, 8]);
+        // red: a run of 8, green: 8 literal bytes, blue: two runs of 4, exponent: run of 8
+        data.extend([128 + 8, 128]);
+        data.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        data.extend([128 + 4, 0, 128 + 4, 64]);
+        data.extend([128 + 8, 129]);
+        fs::write(&path, data).unwrap();
+
+        let (pixels, width, height) = load_hdr(path.to_str().unwrap());
+
+        assert_eq!((width, height), (8, 1));
+        // 128 with exponent 129 is 128 * 2^-7 = 1
+        assert_eq!(pixels[0], Color::new(1.0, 0.0, 0.0));
+        assert_eq!(pixels[7], Color::new(1.0, 112.0 / 128.0, 0.5));
+    }
+}
@@ -0,0 +1,342 @@
+use std::fmt::Debug;
+
+use glam::DVec3;
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    geometry::{Hit, Intersect, Ray},
+    sampling::cosine_hemisphere,
+    tracer::{trace_ray, World},
+};
+
+pub mod bidirectional;
+pub mod photon;
+
+/// Way of computing the color seen along camera rays
+pub trait Integrator: Sync + Send + Debug {
+    /// work done once for the scene before rendering it
+    fn preprocess(&mut self, _world: &World) {}
+
+    /// color of the pixel sample seen along the camera ray `ray`
+    fn radiance(&self, ray: Ray, world: &World) -> Color;
+
+    /// color of the pixel sample along `ray`, also adding to `splats` the light reaching other
+    /// pixels of the image seen from `camera`
+    fn sample(&self, ray: Ray, world: &World, _camera: &Camera, _splats: &mut Splats) -> Color {
+        self.radiance(ray, world)
+    }
+}
+
+/// Light reaching arbitrary pixels of the image, summed over all the samples
+#[derive(Clone, Debug)]
+pub struct Splats {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+/// Unbiased path tracing with direct light sampling, see `tracer::trace_ray`
+#[derive(Clone, Copy, Debug)]
+pub struct PathTracer {
+    /// safety cap on the length of the paths, which end by Russian roulette
+    pub max_reflections: usize,
+}
+
+/// Direct light from every light, plus mirror reflection and refraction, as in Whitted,
+/// "An Improved Illumination Model for Shaded Display". Glossy and diffuse surfaces get no
+/// indirect light
+#[derive(Clone, Copy, Debug)]
+pub struct Whitted {
+    pub max_reflections: usize,
+}
+
+/// Fraction of the hemisphere above the first hit left open by the objects closer than
+/// `distance`, as a gray level
+#[derive(Clone, Copy, Debug)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+    /// rays cast from each hit
+    pub samples: usize,
+}
+
+/// Geometry of the first hit as colors, black where rays miss every object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugView {
+    /// normal mapped from [-1, 1] to [0, 1]
+    Normal,
+    /// distance to the camera, from black at zero to white at `far`
+    Depth { far: f64 },
+    /// texture coordinates wrapped to [0, 1) as red and green
+    Uv,
+    /// barycentric coordinates of triangles as red, green and blue
+    Barycentric,
+}
+
+impl Splats {
+    pub fn new(width: usize, height: usize) -> Self {
+        Splats {
+            width,
+            height,
+            pixels: vec![Color::ZERO; width * height],
+        }
+    }
+
+    pub fn add(&mut self, (x, y): (usize, usize), color: Color) {
+        self.pixels[y * self.width + x] += color;
+    }
+}
+
+impl PathTracer {
+    pub fn new(max_reflections: usize) -> Self {
+        PathTracer { max_reflections }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, ray: Ray, world: &World) -> Color {
+        trace_ray(ray, world, self.max_reflections + 1)
+    }
+}
+
+/// rays Whitted's integrator follows carrying less of the light of the camera ray are dropped,
+/// which keeps glass, splitting every ray in two, from branching without end
+const MIN_WEIGHT: f64 = 1e-3;
+
+impl Whitted {
+    pub fn new(max_reflections: usize) -> Self {
+        Whitted { max_reflections }
+    }
+
+    /// `weight` is the fraction of the light of the camera ray the ray carries
+    fn trace(&self, ray: Ray, world: &World, remaining_reflections: usize, weight: Color) -> Color {
+        let Some(hit) = world.objects.intersect(ray) else {
+            return world
+                .lights
+                .iter()
+                .fold(Color::ZERO, |sum, light| sum + light.le(ray.dir));
+        };
+
+        let emitted = hit.material.emitted();
+        // specular surfaces are followed along every ray they scatter into
+        let scatters = hit.material.specular_scatters(ray, &hit);
+        if !scatters.is_empty() {
+            if remaining_reflections == 0 {
+                return emitted;
+            }
+            return scatters
+                .into_iter()
+                .filter(|scatter| (weight * scatter.attenuation).max_element() >= MIN_WEIGHT)
+                .fold(emitted, |sum, scatter| {
+                    sum + scatter.attenuation
+                        * self.trace(
+                            scatter.ray,
+                            world,
+                            remaining_reflections - 1,
+                            weight * scatter.attenuation,
+                        )
+                });
+        }
+
+        emitted + direct_light(world, ray, &hit)
+    }
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, ray: Ray, world: &World) -> Color {
+        self.trace(ray, world, self.max_reflections, Color::ONE)
+    }
+}
+
+impl AmbientOcclusion {
+    pub fn new(distance: f64, samples: usize) -> Self {
+        AmbientOcclusion { distance, samples }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: Ray, world: &World) -> Color {
+        let Some(hit) = world.objects.intersect(ray) else {
+            return Color::ZERO;
+        };
+
+        // the side of the surface the ray comes from
+        let normal = if hit.normal.dot(ray.dir) > 0.0 {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+        let open = (0..self.samples)
+            .filter(|_| {
+                !world.occluded(hit.spawn_shadow_ray(cosine_hemisphere(normal), self.distance))
+            })
+            .count();
+
+        Color::splat(open as f64 / self.samples as f64)
+    }
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, ray: Ray, world: &World) -> Color {
+        let Some(hit) = world.objects.intersect(ray) else {
+            return Color::ZERO;
+        };
+
+        match *self {
+            DebugView::Normal => 0.5 * (hit.normal + DVec3::ONE),
+            DebugView::Depth { far } => {
+                Color::splat((hit.point.distance(ray.origin) / far).min(1.0))
+            }
+            DebugView::Uv => Color::new(hit.uv.x.rem_euclid(1.0), hit.uv.y.rem_euclid(1.0), 0.0),
+            DebugView::Barycentric => hit.barycentric,
+        }
+    }
+}
+
+/// light arriving at the hit straight from every light, reflected towards the ray origin
+fn direct_light(world: &World, ray: Ray, hit: &Hit) -> Color {
+    world.lights.iter().fold(Color::ZERO, |sum, light| {
+        let Some(sample) = light.sample(hit.point) else {
+            return sum;
+        };
+        if sample.pdf <= 0.0 || world.occluded(hit.spawn_shadow_ray(sample.dir, sample.distance)) {
+            return sum;
+        }
+
+        sum + hit.material.eval(hit, -ray.dir, sample.dir) * sample.radiance / sample.pdf
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::DVec3;
+
+    use crate::{
+        color::Color,
+        geometry::{Intersect, Ray},
+        integrator::{AmbientOcclusion, DebugView, Integrator, Whitted},
+        light::{Light, PointLight},
+        material::{Dielectric, Diffuse, MediumBoundary, Mirror},
+        medium::Medium,
+        object::{sphere::Sphere, triangle::Triangle},
+        tracer::World,
+    };
+
+    fn floor() -> Triangle {
+        Triangle::from_tuples((-10.0, 0.0, 10.0), (10.0, 0.0, 10.0), (0.0, 0.0, -20.0))
+            .with_material(Arc::new(Diffuse::new(Color::splat(0.5))))
+    }
+
+    #[test]
+    fn debug_views_show_the_first_hit() {
+        let scene: Vec<Box<dyn Intersect>> = vec![Box::new(floor())];
+        let world = World::new(&scene);
+        let down = Ray::from_to((0.0, 2.0, 0.0), (0.0, 0.0, 0.0));
+
+        assert!(DebugView::Normal
+            .radiance(down, &world)
+            .abs_diff_eq(Color::new(0.5, 1.0, 0.5), 1e-9));
+        assert!(DebugView::Depth { far: 4.0 }
+            .radiance(down, &world)
+            .abs_diff_eq(Color::splat(0.5), 1e-9));
+
+        let barycentric = DebugView::Barycentric.radiance(down, &world);
+        assert!((barycentric.dot(DVec3::ONE) - 1.0).abs() < 1e-9);
+        assert!(barycentric.min_element() > 0.0);
+
+        let up = Ray::from_to((0.0, 2.0, 0.0), (0.0, 4.0, 0.0));
+        assert_eq!(DebugView::Normal.radiance(up, &world), Color::ZERO);
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_corners() {
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(floor()),
+            // wall along the x axis, standing on the floor
+            Box::new(Triangle::from_tuples(
+                (-10.0, 0.0, 0.0),
+                (10.0, 0.0, 0.0),
+                (0.0, 10.0, 0.0),
+            )),
+        ];
+        let world = World::new(&scene);
+        let ao = AmbientOcclusion::new(1.0, 1000);
+
+        let open = ao.radiance(Ray::from_to((0.0, 2.0, 5.0), (0.0, 0.0, 5.0)), &world);
+        let corner = ao.radiance(Ray::from_to((0.0, 2.0, 0.1), (0.0, 0.0, 0.1)), &world);
+
+        assert_eq!(open, Color::ONE);
+        assert!(corner.x < 0.7, "{corner}");
+    }
+
+    #[test]
+    fn whitted_follows_mirrors_to_direct_light() {
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(floor()),
+            Box::new(
+                Sphere::new((0.0, 1.0, -5.0), 1.0).with_material(Arc::new(Mirror::new(Color::ONE))),
+            ),
+        ];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, 2.0, 0.0),
+            Color::ONE,
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+        let whitted = Whitted::new(4);
+
+        // the diffuse floor right under the light reflects albedo / pi of its irradiance 1 / 4
+        let below_light = whitted.radiance(Ray::from_to((0.0, 1.0, 1.0), (0.0, 0.0, 0.0)), &world);
+        assert!(below_light.abs_diff_eq(Color::splat(0.5 / std::f64::consts::PI / 4.0), 1e-9));
+
+        // seen in the mirror, the floor keeps its color
+        let seen_in_mirror =
+            whitted.radiance(Ray::from_to((0.0, 1.0, 0.0), (0.0, 0.5, -5.0)), &world);
+        assert!(seen_in_mirror.x > 0.0);
+    }
+
+    #[test]
+    fn whitted_follows_both_rays_of_glass() {
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(floor()),
+            Box::new(
+                Sphere::new((0.0, 1.0, -3.0), 1.0)
+                    .with_material(Arc::new(Dielectric::new(Color::ONE, 1.5))),
+            ),
+        ];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, 4.0, 0.0),
+            Color::ONE,
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+        let whitted = Whitted::new(16);
+        let into_glass = Ray::from_to((0.0, 1.5, 1.0), (0.0, 1.2, -3.0));
+
+        let first = whitted.radiance(into_glass, &world);
+        assert!(first.x > 0.0);
+        for _ in 0..20 {
+            assert_eq!(whitted.radiance(into_glass, &world), first);
+        }
+    }
+
+    #[test]
+    fn whitted_sees_through_media() {
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(floor()),
+            // fog around the light
+            Box::new(Sphere::new((0.0, 2.0, 0.0), 0.5).with_material(Arc::new(
+                MediumBoundary::new(Medium::fog(Color::ONE, 1.0, 0.0)),
+            ))),
+        ];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, 2.0, 0.0),
+            Color::ONE,
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+
+        let below_light =
+            Whitted::new(4).radiance(Ray::from_to((0.0, 1.0, 1.0), (0.0, 0.0, 0.0)), &world);
+        assert!(below_light.abs_diff_eq(Color::splat(0.5 / std::f64::consts::PI / 4.0), 1e-9));
+    }
+}
@@ -0,0 +1,691 @@
+use std::f64::consts::PI;
+
+use glam::DVec3;
+use nanorand::Rng;
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    geometry::{Hit, Intersect, Ray},
+    integrator::{Integrator, Splats},
+    light::{AreaLight, Light},
+    tracer::World,
+};
+
+/// Bidirectional path tracing as in Veach, "Robust Monte Carlo Methods for Light Transport
+/// Simulation": a path started from the camera and one started from a light are joined at
+/// every pair of their vertices, and the strategies are weighted with multiple importance
+/// sampling. Paths from the lights reaching the camera directly are splatted to the image
+#[derive(Clone, Copy, Debug)]
+pub struct Bidirectional {
+    /// most bounces of the joined paths
+    pub max_depth: usize,
+}
+
+/// What a path vertex lies on
+#[derive(Clone, Copy, Debug)]
+enum VertexKind<'w> {
+    Camera,
+    /// point sampled on a light, or the disk infinite lights send their rays from
+    Light(&'w dyn Light),
+    Surface(Hit<'w>),
+    /// camera paths leaving the scene, towards the infinite lights
+    Background,
+}
+
+/// Vertex of a camera or light path
+#[derive(Clone, Copy, Debug)]
+struct Vertex<'w> {
+    kind: VertexKind<'w>,
+    point: DVec3,
+    /// surface normal, zero for vertices that are not on a surface
+    normal: DVec3,
+    /// direction towards the previous vertex of the path
+    wo: DVec3,
+    /// radiance or importance carried by the path up to the vertex, over its density
+    beta: Color,
+    /// if the direction leaving the vertex comes from a delta distribution
+    delta: bool,
+    /// area density of the vertex, sampled from the previous vertex of its path
+    pdf_fwd: f64,
+    /// area density of the vertex if it were sampled from the next vertex instead
+    pdf_rev: f64,
+}
+
+/// Scene the paths are traced in
+struct Context<'w> {
+    world: &'w World<'w>,
+    /// camera and resolution of the image, when light paths are splatted to it
+    camera: Option<(&'w Camera, (usize, usize))>,
+    /// bounding sphere of the objects
+    center: DVec3,
+    radius: f64,
+}
+
+impl Bidirectional {
+    pub fn new(max_depth: usize) -> Self {
+        Bidirectional { max_depth }
+    }
+
+    /// radiance along the camera ray. With `splats`, light paths joined to the camera are
+    /// added to the image, otherwise those strategies are left out
+    fn estimate(
+        &self,
+        ray: Ray,
+        world: &World,
+        camera: Option<&Camera>,
+        mut splats: Option<&mut Splats>,
+    ) -> Color {
+        let bounds = world.objects.bounds();
+        let context = Context {
+            world,
+            camera: camera.zip(splats.as_ref().map(|splats| (splats.width, splats.height))),
+            center: (bounds.min + bounds.max) / 2.0,
+            radius: bounds.min.distance(bounds.max) / 2.0,
+        };
+
+        // without light paths reaching the camera, the density of camera rays is never used
+        let camera_pdf = context
+            .camera
+            .map_or(1.0, |(camera, _)| camera.pdf(ray.dir));
+        let mut camera_path = vec![Vertex::new(VertexKind::Camera, ray.origin, Color::ONE)];
+        context.random_walk(
+            ray,
+            Color::ONE,
+            camera_pdf,
+            self.max_depth + 2,
+            &mut camera_path,
+        );
+        let light_path = context.light_path(self.max_depth + 1);
+
+        let mut radiance = Color::ZERO;
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                // a light seen by the camera is found by camera paths
+                if s + t < 2 || s + t - 2 > self.max_depth || (s == 1 && t == 1) {
+                    continue;
+                }
+                if t == 1 && context.camera.is_none() {
+                    continue;
+                }
+
+                let (color, pixel) = context.connect(&light_path[..s], &camera_path[..t]);
+                match (pixel, splats.as_deref_mut()) {
+                    (Some(pixel), Some(splats)) => splats.add(pixel, color),
+                    _ => radiance += color,
+                }
+            }
+        }
+
+        radiance
+    }
+}
+
+impl Integrator for Bidirectional {
+    fn radiance(&self, ray: Ray, world: &World) -> Color {
+        self.estimate(ray, world, None, None)
+    }
+
+    fn sample(&self, ray: Ray, world: &World, camera: &Camera, splats: &mut Splats) -> Color {
+        self.estimate(ray, world, Some(camera), Some(splats))
+    }
+}
+
+impl<'w> Vertex<'w> {
+    fn new(kind: VertexKind<'w>, point: DVec3, beta: Color) -> Self {
+        Vertex {
+            kind,
+            point,
+            normal: DVec3::ZERO,
+            wo: DVec3::ZERO,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_infinite_light(&self) -> bool {
+        match self.kind {
+            VertexKind::Light(light) => light.is_infinite(),
+            VertexKind::Background => true,
+            _ => false,
+        }
+    }
+
+    fn is_delta_light(&self) -> bool {
+        matches!(self.kind, VertexKind::Light(light) if light.is_delta())
+    }
+
+    /// light emitted by the vertex of a camera path
+    fn le(&self, world: &World) -> Color {
+        match self.kind {
+            VertexKind::Surface(hit) => hit.material.emitted(),
+            VertexKind::Background => world
+                .lights
+                .iter()
+                .fold(Color::ZERO, |sum, light| sum + light.le(-self.wo)),
+            _ => Color::ZERO,
+        }
+    }
+
+    /// area density at `next` of the direction to it sampled from this vertex with solid angle
+    /// density `pdf`. Infinite lights keep solid angle densities
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        if next.is_infinite_light() {
+            return pdf;
+        }
+
+        let to_next = next.point - self.point;
+        let distance_squared = to_next.length_squared();
+        let cos = if next.normal == DVec3::ZERO {
+            1.0
+        } else {
+            next.normal.dot(to_next).abs() / distance_squared.sqrt()
+        };
+        pdf * cos / distance_squared
+    }
+}
+
+impl<'w> Context<'w> {
+    /// probability of choosing each light
+    fn light_selection(&self) -> f64 {
+        1.0 / self.world.lights.len() as f64
+    }
+
+    /// adds to `path` the vertices found by following `ray` and the directions the materials
+    /// scatter it to, until the path has `max_vertices`. `pdf` is the solid angle density of
+    /// the ray direction. Camera paths leaving the scene end on a background vertex
+    fn random_walk(
+        &self,
+        mut ray: Ray,
+        mut beta: Color,
+        mut pdf: f64,
+        max_vertices: usize,
+        path: &mut Vec<Vertex<'w>>,
+    ) {
+        let world = self.world;
+        let is_camera_path = matches!(path[0].kind, VertexKind::Camera);
+
+        while path.len() < max_vertices {
+            let Some(hit) = world.objects.intersect(ray) else {
+                if is_camera_path {
+                    let mut background = Vertex::new(
+                        VertexKind::Background,
+                        ray.origin + 2.0 * self.radius * ray.dir,
+                        beta,
+                    );
+                    background.wo = -ray.dir;
+                    background.pdf_fwd = pdf;
+                    path.push(background);
+                }
+                break;
+            };
+
+            let mut vertex = Vertex::new(VertexKind::Surface(hit), hit.point, beta);
+            vertex.normal = hit.geometric_normal;
+            vertex.wo = -ray.dir;
+            vertex.pdf_fwd = path[path.len() - 1].convert_density(pdf, &vertex);
+            path.push(vertex);
+            if path.len() == max_vertices {
+                break;
+            }
+
+            let Some(scatter) = hit.material.scatter(ray, &hit) else {
+                break;
+            };
+            // densities of the way forward and of the way back, unknown for delta distributions
+            let (pdf_fwd, pdf_rev) = if scatter.specular {
+                (0.0, 0.0)
+            } else {
+                (
+                    hit.material.pdf(&hit, -ray.dir, scatter.ray.dir),
+                    hit.material.pdf(&hit, scatter.ray.dir, -ray.dir),
+                )
+            };
+
+            beta *= scatter.attenuation;
+            if beta == Color::ZERO {
+                break;
+            }
+
+            if let [.., prev, current] = path.as_mut_slice() {
+                current.delta = scatter.specular;
+                prev.pdf_rev = current.convert_density(pdf_rev, prev);
+            }
+            pdf = pdf_fwd;
+            ray = scatter.ray;
+        }
+    }
+
+    /// path starting from a random light, with at most `max_vertices`
+    fn light_path(&self, max_vertices: usize) -> Vec<Vertex<'w>> {
+        let lights = &self.world.lights;
+        let mut path = Vec::with_capacity(max_vertices);
+        if lights.is_empty() || max_vertices == 0 {
+            return path;
+        }
+
+        let light: &dyn Light =
+            lights[nanorand::tls_rng().generate_range(0..lights.len())].as_ref();
+        let Some(emission) = light.sample_emission(self.center, self.radius) else {
+            return path;
+        };
+        if emission.pdf_pos <= 0.0 || emission.pdf_dir <= 0.0 || emission.radiance == Color::ZERO {
+            return path;
+        }
+
+        let dir = emission.ray.dir;
+        let cos = if emission.normal == DVec3::ZERO {
+            1.0
+        } else {
+            emission.normal.dot(dir).abs()
+        };
+        let pdf_pos = emission.pdf_pos * self.light_selection();
+
+        let mut origin = Vertex::new(
+            VertexKind::Light(light),
+            emission.ray.origin,
+            emission.radiance / pdf_pos,
+        );
+        origin.normal = emission.normal;
+        origin.pdf_fwd = pdf_pos;
+        path.push(origin);
+
+        let beta = emission.radiance * cos / (pdf_pos * emission.pdf_dir);
+        self.random_walk(
+            emission.ray,
+            beta,
+            emission.pdf_dir,
+            max_vertices,
+            &mut path,
+        );
+
+        // the disk infinite lights start from is not part of the scene: the first hit has the
+        // density of points of the disk, and the light the density of its direction
+        if light.is_infinite() {
+            if let Some(first) = path.get_mut(1) {
+                first.pdf_fwd = emission.pdf_pos;
+                if first.normal != DVec3::ZERO {
+                    first.pdf_fwd *= first.normal.dot(dir).abs();
+                }
+            }
+            path[0].pdf_fwd = self.infinite_light_pdf(-dir);
+        }
+
+        path
+    }
+
+    /// light carried by the path joining the first `s` vertices of the light path to the first
+    /// `t` of the camera path, weighted against the other strategies making the same path.
+    /// Light paths joined to the camera also return the pixel they reach
+    fn connect(
+        &self,
+        light_path: &[Vertex<'w>],
+        camera_path: &[Vertex<'w>],
+    ) -> (Color, Option<(usize, usize)>) {
+        let (s, t) = (light_path.len(), camera_path.len());
+        let world = self.world;
+        let pt = &camera_path[t - 1];
+        let nothing = (Color::ZERO, None);
+        if t > 1 && s != 0 && matches!(pt.kind, VertexKind::Background) {
+            return nothing;
+        }
+
+        let mut sampled = None;
+        let mut pixel = None;
+        let radiance = if s == 0 {
+            // the camera path found a light
+            pt.beta * pt.le(world)
+        } else if t == 1 {
+            // the light path is seen by the camera
+            let qs = &light_path[s - 1];
+            let (Some((camera, resolution)), VertexKind::Surface(hit)) = (self.camera, qs.kind)
+            else {
+                return nothing;
+            };
+            let Some(seen_at) = camera.project(qs.point, resolution) else {
+                return nothing;
+            };
+
+            let to_camera = camera.origin - qs.point;
+            let distance = to_camera.length();
+            let dir = to_camera / distance;
+            if world.occluded(hit.spawn_shadow_ray(dir, distance)) {
+                return nothing;
+            }
+
+            // importance of the camera for the direction, per unit area at the vertex
+            let importance = camera.pdf(-dir) / (distance * distance);
+            pixel = Some(seen_at);
+            sampled = Some(Vertex::new(
+                VertexKind::Camera,
+                camera.origin,
+                Color::splat(importance),
+            ));
+            qs.beta * hit.material.eval(&hit, qs.wo, dir) * importance
+        } else if s == 1 {
+            // a light sampled from the camera path
+            let VertexKind::Surface(hit) = pt.kind else {
+                return nothing;
+            };
+            let light =
+                world.lights[nanorand::tls_rng().generate_range(0..world.lights.len())].as_ref();
+            let Some(sample) = light.sample(pt.point) else {
+                return nothing;
+            };
+            if sample.pdf <= 0.0 {
+                return nothing;
+            }
+
+            let point = if light.is_infinite() {
+                pt.point + 2.0 * self.radius * sample.dir
+            } else {
+                pt.point + sample.distance * sample.dir
+            };
+            let mut vertex = Vertex::new(
+                VertexKind::Light(light),
+                point,
+                sample.radiance / (sample.pdf * self.light_selection()),
+            );
+            vertex.normal = sample.normal;
+            vertex.pdf_fwd = self.pdf_light_origin(&vertex, pt);
+
+            let reflected = pt.beta * hit.material.eval(&hit, pt.wo, sample.dir);
+            if reflected == Color::ZERO
+                || world.occluded(hit.spawn_shadow_ray(sample.dir, sample.distance))
+            {
+                return nothing;
+            }
+            sampled = Some(vertex);
+            reflected * vertex.beta
+        } else {
+            // two points of the scene seeing each other
+            let qs = &light_path[s - 1];
+            let (VertexKind::Surface(light_hit), VertexKind::Surface(camera_hit)) =
+                (qs.kind, pt.kind)
+            else {
+                return nothing;
+            };
+
+            let to_light = qs.point - pt.point;
+            let distance = to_light.length();
+            let dir = to_light / distance;
+            let radiance = qs.beta
+                * light_hit.material.eval(&light_hit, qs.wo, -dir)
+                * camera_hit.material.eval(&camera_hit, pt.wo, dir)
+                * pt.beta
+                / (distance * distance);
+            if radiance == Color::ZERO || world.occluded(camera_hit.spawn_shadow_ray(dir, distance))
+            {
+                return nothing;
+            }
+            radiance
+        };
+
+        if radiance == Color::ZERO {
+            return nothing;
+        }
+        (
+            radiance * self.mis_weight(light_path, camera_path, sampled),
+            pixel,
+        )
+    }
+
+    /// power heuristic weight of the strategy joining `light_path` and `camera_path`, against
+    /// all the other ways of sampling the same path. `sampled` replaces the last vertex of the
+    /// path of length one
+    fn mis_weight(
+        &self,
+        light_path: &[Vertex<'w>],
+        camera_path: &[Vertex<'w>],
+        sampled: Option<Vertex<'w>>,
+    ) -> f64 {
+        let (s, t) = (light_path.len(), camera_path.len());
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        // the last two vertices of each path, with the densities of sampling them the other way
+        let mut qs = light_path.last().copied();
+        let mut qs_minus = s.checked_sub(2).map(|i| light_path[i]);
+        let mut pt = camera_path[t - 1];
+        let mut pt_minus = t.checked_sub(2).map(|i| camera_path[i]);
+        if s == 1 {
+            qs = sampled;
+        } else if t == 1 {
+            pt = sampled.unwrap_or(pt);
+        }
+
+        // the vertices joined are sampled in both directions
+        pt.delta = false;
+        pt.pdf_rev = match qs {
+            Some(qs) => self.pdf(&qs, qs_minus.as_ref(), &pt),
+            None => pt_minus.map_or(0.0, |pt_minus| self.pdf_light_origin(&pt, &pt_minus)),
+        };
+        if let Some(pt_minus) = &mut pt_minus {
+            pt_minus.pdf_rev = match qs {
+                Some(qs) => self.pdf(&pt, Some(&qs), pt_minus),
+                None => self.pdf_light(&pt, pt_minus),
+            };
+        }
+        if let Some(qs) = &mut qs {
+            qs.delta = false;
+            qs.pdf_rev = self.pdf(&pt, pt_minus.as_ref(), qs);
+            if let Some(qs_minus) = &mut qs_minus {
+                qs_minus.pdf_rev = self.pdf(qs, Some(&pt), qs_minus);
+            }
+        }
+
+        let camera_vertex = |i: usize| match t - 1 - i {
+            0 => &pt,
+            1 => pt_minus.as_ref().unwrap(),
+            _ => &camera_path[i],
+        };
+        let light_vertex = |i: usize| match s - 1 - i {
+            0 => qs.as_ref().unwrap(),
+            1 => qs_minus.as_ref().unwrap(),
+            _ => &light_path[i],
+        };
+        // zero densities come from delta distributions, which the checks below leave out
+        let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+
+        // ratios of the densities of the other strategies to this one, moving the joint
+        // towards the camera and then towards the light. The camera itself can only be
+        // reached by light paths that are splatted
+        let mut sum = 0.0;
+        let last_camera_vertex = if self.camera.is_some() { 1 } else { 2 };
+        let mut ratio = 1.0;
+        for i in (last_camera_vertex..t).rev() {
+            let vertex = camera_vertex(i);
+            ratio *= remap(vertex.pdf_rev) / remap(vertex.pdf_fwd);
+            if !vertex.delta && !camera_vertex(i - 1).delta {
+                sum += ratio * ratio;
+            }
+        }
+
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            let vertex = light_vertex(i);
+            ratio *= remap(vertex.pdf_rev) / remap(vertex.pdf_fwd);
+            let delta_before = if i > 0 {
+                light_vertex(i - 1).delta
+            } else {
+                vertex.is_delta_light()
+            };
+            if !vertex.delta && !delta_before {
+                sum += ratio * ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+
+    /// area density of `vertex` sampling `next`, having come from `prev`
+    fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let to_next = (next.point - vertex.point).normalize();
+        let pdf = match vertex.kind {
+            VertexKind::Light(_) => return self.pdf_light(vertex, next),
+            VertexKind::Camera => self.camera.map_or(0.0, |(camera, _)| camera.pdf(to_next)),
+            VertexKind::Surface(hit) => prev.map_or(0.0, |prev| {
+                let to_prev = (prev.point - vertex.point).normalize();
+                hit.material.pdf(&hit, to_prev, to_next)
+            }),
+            VertexKind::Background => 0.0,
+        };
+        vertex.convert_density(pdf, next)
+    }
+
+    /// area density of a light path leaving the light at `vertex` towards `next`
+    fn pdf_light(&self, vertex: &Vertex, next: &Vertex) -> f64 {
+        let to_next = next.point - vertex.point;
+        let distance_squared = to_next.length_squared();
+        let dir = to_next / distance_squared.sqrt();
+
+        let pdf = if vertex.is_infinite_light() {
+            // rays of infinite lights leave a disk covering the scene
+            1.0 / (PI * self.radius * self.radius)
+        } else {
+            emission_pdf(vertex, dir).1 / distance_squared
+        };
+
+        if next.normal == DVec3::ZERO {
+            pdf
+        } else {
+            pdf * next.normal.dot(dir).abs()
+        }
+    }
+
+    /// density of light paths starting at `vertex` on a light, lit towards `next`. For
+    /// infinite lights, the solid angle density of the direction
+    fn pdf_light_origin(&self, vertex: &Vertex, next: &Vertex) -> f64 {
+        let dir = (next.point - vertex.point).normalize();
+        if vertex.is_infinite_light() {
+            return self.infinite_light_pdf(-dir);
+        }
+
+        emission_pdf(vertex, dir).0 * self.light_selection()
+    }
+
+    /// solid angle density of light paths coming from `dir`, summed over the infinite lights
+    fn infinite_light_pdf(&self, dir: DVec3) -> f64 {
+        self.world
+            .lights
+            .iter()
+            .filter(|light| light.is_infinite())
+            .map(|light| light.pdf(self.center, dir))
+            .sum::<f64>()
+            * self.light_selection()
+    }
+}
+
+/// densities of the light at `vertex` emitting towards `dir`, see `Light::emission_pdf`
+fn emission_pdf(vertex: &Vertex, dir: DVec3) -> (f64, f64) {
+    match vertex.kind {
+        VertexKind::Light(light) => light.emission_pdf(vertex.normal, dir),
+        VertexKind::Surface(hit) => AreaLight::new(hit.object)
+            .map_or((0.0, 0.0), |light| light.emission_pdf(vertex.normal, dir)),
+        _ => (0.0, 0.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use glam::DVec3;
+
+    use crate::{
+        camera::Camera,
+        color::Color,
+        geometry::Ray,
+        integrator::{bidirectional::Bidirectional, Integrator, PathTracer},
+        light::PointLight,
+        material::{Diffuse, Emissive},
+        object::triangle::Triangle,
+        scene::Scene,
+        tracer::{render, World},
+    };
+
+    /// diffuse floor and wall lit by a point light and an emissive triangle facing the floor
+    fn room() -> Scene {
+        let gray = Arc::new(Diffuse::new(Color::splat(0.5)));
+        Scene {
+            objects: vec![
+                Box::new(
+                    Triangle::from_tuples((-10.0, 0.0, 10.0), (10.0, 0.0, 10.0), (0.0, 0.0, -20.0))
+                        .with_material(gray.clone()),
+                ),
+                Box::new(
+                    Triangle::from_tuples((-10.0, 0.0, -2.0), (10.0, 0.0, -2.0), (0.0, 10.0, -2.0))
+                        .with_material(gray),
+                ),
+                Box::new(
+                    Triangle::from_tuples((-1.0, 2.0, -1.0), (0.0, 2.0, 1.0), (1.0, 2.0, -1.0))
+                        .with_material(Arc::new(Emissive::new(Color::splat(4.0)))),
+                ),
+            ],
+            lights: vec![Box::new(PointLight::new(
+                DVec3::new(2.0, 1.0, 0.0),
+                Color::ONE,
+            ))],
+            medium: None,
+            camera: Camera::new(
+                DVec3::new(0.0, 1.5, 4.0),
+                DVec3::new(0.0, -0.8, -1.0).normalize(),
+                DVec3::Y,
+                60.0f64.to_radians(),
+                60.0f64.to_radians(),
+                1.0,
+            ),
+        }
+    }
+
+    fn mean_radiance(integrator: &dyn Integrator, ray: Ray, world: &World, n: usize) -> f64 {
+        (0..n)
+            .map(|_| integrator.radiance(ray, world).x)
+            .sum::<f64>()
+            / n as f64
+    }
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        let scene = room();
+        let world = World::new(&scene.objects).with_lights(&scene.lights);
+        let max_depth = 4;
+
+        for ray in [
+            Ray::from_to((0.0, 1.0, 3.0), (0.0, 0.0, 0.0)),
+            Ray::from_to((0.0, 1.0, 3.0), (1.0, 1.0, -2.0)),
+        ] {
+            let expected = mean_radiance(&PathTracer::new(max_depth), ray, &world, 20_000);
+            let bidirectional = mean_radiance(&Bidirectional::new(max_depth), ray, &world, 20_000);
+            assert!(
+                (bidirectional - expected).abs() < 0.05 * expected,
+                "{bidirectional} {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn light_paths_splatted_to_the_image_keep_its_brightness() {
+        // the image is noisier with the point light close to the wall
+        let scene = Scene {
+            lights: vec![],
+            ..room()
+        };
+        let (x_res, y_res) = (8, 8);
+        let mean = |integrator: &mut dyn Integrator| {
+            let mut image = vec![Color::ZERO; x_res * y_res];
+            render(&scene, x_res, y_res, 2, 256, integrator, &mut image);
+            image.iter().map(|color| color.x).sum::<f64>() / image.len() as f64
+        };
+
+        let expected = mean(&mut PathTracer::new(4));
+        let bidirectional = mean(&mut Bidirectional::new(4));
+        assert!(
+            (bidirectional - expected).abs() < 0.05 * expected,
+            "{bidirectional} {expected}"
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use std::{f64::consts::PI, ops::Range};
+
+use glam::DVec3;
+use nanorand::Rng;
+
+use crate::{
+    color::Color,
+    geometry::{Hit, Intersect, Ray},
+    integrator::{direct_light, Integrator},
+    tracer::World,
+};
+
+/// Photons sent for the caustic map per photon it should hold, at most. Scenes with few
+/// specular objects fill it with fewer photons
+const MAX_EMITTED_PER_CAUSTIC: usize = 32;
+
+/// Photon mapping as in Jensen, "Global Illumination Using Photon Maps": before rendering,
+/// photons are sent from the lights and stored where they land on non-specular surfaces.
+/// Camera rays follow mirrors and glass to the first other surface, which gets the direct
+/// light, the caustics estimated from the photons that only went through specular surfaces,
+/// and the rest of the indirect light from one gather ray reading the global photon map
+#[derive(Clone, Debug)]
+pub struct PhotonMapper {
+    /// photons sent from the lights for the global map
+    pub photons: usize,
+    /// photons the caustic map should hold
+    pub caustic_photons: usize,
+    /// photons the light leaving a point is estimated from
+    pub neighbors: usize,
+    /// largest distance to the photons the light leaving a point is estimated from
+    pub radius: f64,
+    /// most bounces of photons and of rays through specular surfaces
+    pub max_depth: usize,
+    global: PhotonMap,
+    caustics: PhotonMap,
+}
+
+/// Light arriving at a surface
+#[derive(Clone, Copy, Debug)]
+struct Photon {
+    point: DVec3,
+    /// direction the light comes from
+    wi: DVec3,
+    power: Color,
+}
+
+/// Photons in a kd-tree: the middle photon of each range splits it along its axis, the
+/// photons before it are on the lower side
+#[derive(Clone, Debug, Default)]
+struct PhotonMap {
+    photons: Vec<Photon>,
+    /// axis each photon splits its range along
+    axes: Vec<usize>,
+}
+
+impl PhotonMapper {
+    pub fn new(photons: usize, neighbors: usize, radius: f64) -> Self {
+        PhotonMapper {
+            photons,
+            caustic_photons: photons,
+            neighbors,
+            radius,
+            max_depth: 16,
+            global: PhotonMap::default(),
+            caustics: PhotonMap::default(),
+        }
+    }
+
+    pub fn with_caustic_photons(mut self, caustic_photons: usize) -> Self {
+        self.caustic_photons = caustic_photons;
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// sends one photon from a random light, storing it in `photons` where it lands on
+    /// non-specular surfaces. With `caustics_only`, it is only stored on the first one and only
+    /// if it went through specular surfaces before. The power is that of a single photon
+    fn emit(
+        &self,
+        world: &World,
+        (center, radius): (DVec3, f64),
+        caustics_only: bool,
+        photons: &mut Vec<Photon>,
+    ) {
+        let lights = &world.lights;
+        if lights.is_empty() {
+            return;
+        }
+
+        let mut rng = nanorand::tls_rng();
+        let light = lights[rng.generate_range(0..lights.len())].as_ref();
+        let Some(emission) = light.sample_emission(center, radius) else {
+            return;
+        };
+        if emission.pdf_pos <= 0.0 || emission.pdf_dir <= 0.0 {
+            return;
+        }
+
+        let mut ray = emission.ray;
+        let cos = if emission.normal == DVec3::ZERO {
+            1.0
+        } else {
+            emission.normal.dot(ray.dir).abs()
+        };
+        let mut power =
+            emission.radiance * cos * lights.len() as f64 / (emission.pdf_pos * emission.pdf_dir);
+
+        let mut bounces = 0;
+        while bounces <= self.max_depth {
+            let Some(hit) = world.objects.intersect(ray) else {
+                return;
+            };
+            let Some(scatter) = hit.material.scatter(ray, &hit) else {
+                return;
+            };
+
+            // surfaces bounding media let the light through unchanged
+            if hit.material.medium().is_some() {
+                ray = scatter.ray;
+                continue;
+            }
+
+            if !scatter.specular {
+                let photon = Photon {
+                    point: hit.point,
+                    wi: -ray.dir,
+                    power,
+                };
+                if caustics_only {
+                    if bounces > 0 {
+                        photons.push(photon);
+                    }
+                    return;
+                }
+                photons.push(photon);
+            }
+
+            // Russian roulette keeps the power of the photons left close to the emitted one
+            let survival = scatter.attenuation.max_element().min(1.0);
+            if rng.generate::<f64>() >= survival {
+                return;
+            }
+            power *= scatter.attenuation / survival;
+            ray = scatter.ray;
+            bounces += 1;
+        }
+    }
+
+    /// light reflected towards the camera by the first non-specular surface seen through
+    /// `ray`, found from the global photon map. Light sources are left to direct lighting and
+    /// the caustic map
+    fn gather(&self, mut ray: Ray, world: &World) -> Color {
+        let mut throughput = Color::ONE;
+        for _ in 0..=self.max_depth {
+            let Some(hit) = world.objects.intersect(ray) else {
+                return Color::ZERO;
+            };
+            let Some(scatter) = hit.material.scatter(ray, &hit) else {
+                return Color::ZERO;
+            };
+
+            if !scatter.specular {
+                return throughput
+                    * self
+                        .global
+                        .estimate(&hit, -ray.dir, self.neighbors, self.radius);
+            }
+            throughput *= scatter.attenuation;
+            ray = scatter.ray;
+        }
+
+        Color::ZERO
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn preprocess(&mut self, world: &World) {
+        let bounds = world.objects.bounds();
+        let scene = (
+            (bounds.min + bounds.max) / 2.0,
+            bounds.min.distance(bounds.max) / 2.0,
+        );
+
+        let mut global = Vec::new();
+        for _ in 0..self.photons {
+            self.emit(world, scene, false, &mut global);
+        }
+        for photon in &mut global {
+            photon.power /= self.photons as f64;
+        }
+
+        let mut caustics = Vec::new();
+        let mut emitted = 0;
+        while caustics.len() < self.caustic_photons
+            && emitted < MAX_EMITTED_PER_CAUSTIC * self.caustic_photons
+        {
+            // scenes where as many photons as the global map has found no caustic have none
+            if caustics.is_empty() && emitted >= self.photons.max(self.caustic_photons) {
+                break;
+            }
+            self.emit(world, scene, true, &mut caustics);
+            emitted += 1;
+        }
+        for photon in &mut caustics {
+            photon.power /= emitted as f64;
+        }
+
+        self.global = PhotonMap::new(global);
+        self.caustics = PhotonMap::new(caustics);
+    }
+
+    fn radiance(&self, mut ray: Ray, world: &World) -> Color {
+        let mut throughput = Color::ONE;
+        let mut radiance = Color::ZERO;
+        for _ in 0..=self.max_depth {
+            let Some(hit) = world.objects.intersect(ray) else {
+                let background = world
+                    .lights
+                    .iter()
+                    .fold(Color::ZERO, |sum, light| sum + light.le(ray.dir));
+                return radiance + throughput * background;
+            };
+
+            radiance += throughput * hit.material.emitted();
+            let Some(scatter) = hit.material.scatter(ray, &hit) else {
+                return radiance;
+            };
+
+            if !scatter.specular {
+                let wo = -ray.dir;
+                let caustics = self
+                    .caustics
+                    .estimate(&hit, wo, self.neighbors, self.radius);
+                let indirect = scatter.attenuation * self.gather(scatter.ray, world);
+                return radiance
+                    + throughput * (direct_light(world, ray, &hit) + caustics + indirect);
+            }
+            throughput *= scatter.attenuation;
+            ray = scatter.ray;
+        }
+
+        radiance
+    }
+}
+
+impl PhotonMap {
+    fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        balance(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    /// indices of the `count` photons closest to `point`, at most `max_distance` away, and
+    /// their squared distances, closest first
+    fn nearest(&self, point: DVec3, count: usize, max_distance: f64) -> Vec<(f64, usize)> {
+        let mut nearest = Vec::with_capacity(count + 1);
+        let mut max_distance_squared = max_distance * max_distance;
+        if count > 0 {
+            self.search(
+                0..self.photons.len(),
+                point,
+                count,
+                &mut max_distance_squared,
+                &mut nearest,
+            );
+        }
+        nearest
+    }
+
+    fn search(
+        &self,
+        range: Range<usize>,
+        point: DVec3,
+        count: usize,
+        max_distance_squared: &mut f64,
+        nearest: &mut Vec<(f64, usize)>,
+    ) {
+        if range.is_empty() {
+            return;
+        }
+
+        let middle = range.start + range.len() / 2;
+        let photon = &self.photons[middle];
+        let axis = self.axes[middle];
+        let offset = point[axis] - photon.point[axis];
+        let (near, far) = if offset < 0.0 {
+            (range.start..middle, middle + 1..range.end)
+        } else {
+            (middle + 1..range.end, range.start..middle)
+        };
+
+        self.search(near, point, count, max_distance_squared, nearest);
+
+        let distance_squared = point.distance_squared(photon.point);
+        if distance_squared < *max_distance_squared {
+            let index = nearest.partition_point(|&(other, _)| other <= distance_squared);
+            nearest.insert(index, (distance_squared, middle));
+            // once full, only closer photons than the farthest one are looked for
+            if nearest.len() > count {
+                nearest.pop();
+            }
+            if nearest.len() == count {
+                *max_distance_squared = nearest[count - 1].0;
+            }
+        }
+
+        if offset * offset < *max_distance_squared {
+            self.search(far, point, count, max_distance_squared, nearest);
+        }
+    }
+
+    /// light carried by the photons around the hit, reflected towards `wo`. The photons are
+    /// spread over the disk reaching the farthest one, or the whole search disk if fewer than
+    /// `count` are found
+    fn estimate(&self, hit: &Hit, wo: DVec3, count: usize, max_distance: f64) -> Color {
+        let nearest = self.nearest(hit.point, count, max_distance);
+        let radius_squared = if nearest.len() == count {
+            nearest[count - 1].0
+        } else {
+            max_distance * max_distance
+        };
+        if radius_squared <= 0.0 {
+            return Color::ZERO;
+        }
+
+        // the BSDF includes the cosine, which the power of the photons already has
+        let reflected = nearest.iter().fold(Color::ZERO, |sum, &(_, index)| {
+            let photon = &self.photons[index];
+            let cos = hit.normal.dot(photon.wi).abs();
+            if cos <= 0.0 {
+                return sum;
+            }
+            sum + hit.material.eval(hit, wo, photon.wi) / cos * photon.power
+        });
+        reflected / (PI * radius_squared)
+    }
+}
+
+/// orders `photons` into a kd-tree, splitting each range at its middle along the axis its
+/// photons spread the most on
+fn balance(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.len() <= 1 {
+        return;
+    }
+
+    let (min, max) = photons.iter().fold(
+        (DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)),
+        |(min, max), photon| (min.min(photon.point), max.max(photon.point)),
+    );
+    let extent = max - min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+
+    let middle = photons.len() / 2;
+    photons.select_nth_unstable_by(middle, |a, b| a.point[axis].total_cmp(&b.point[axis]));
+    axes[middle] = axis;
+
+    let (lower, upper) = photons.split_at_mut(middle);
+    let (lower_axes, upper_axes) = axes.split_at_mut(middle);
+    balance(lower, lower_axes);
+    balance(&mut upper[1..], &mut upper_axes[1..]);
+}
+
+#[cfg(test)]
+mod test {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use glam::DVec3;
+
+    use crate::{
+        color::Color,
+        geometry::{Intersect, Ray},
+        integrator::{
+            photon::{Photon, PhotonMap, PhotonMapper},
+            Integrator, PathTracer,
+        },
+        light::{Light, PointLight},
+        material::{Dielectric, Diffuse},
+        object::{sphere::Sphere, triangle::Triangle},
+        sampling::uniform_sphere,
+        tracer::World,
+    };
+
+    fn floor() -> Box<dyn Intersect> {
+        Box::new(
+            Triangle::from_tuples((-20.0, 0.0, 20.0), (20.0, 0.0, 20.0), (0.0, 0.0, -40.0))
+                .with_material(Arc::new(Diffuse::new(Color::splat(0.5)))),
+        )
+    }
+
+    #[test]
+    fn kd_tree_finds_the_nearest_photons() {
+        let photons: Vec<Photon> = (0..2000)
+            .map(|_| Photon {
+                point: uniform_sphere() * DVec3::new(3.0, 1.0, 0.5),
+                wi: DVec3::Y,
+                power: Color::ONE,
+            })
+            .collect();
+        let map = PhotonMap::new(photons);
+
+        for _ in 0..50 {
+            let point = uniform_sphere();
+            let mut expected: Vec<f64> = map
+                .photons
+                .iter()
+                .map(|photon| point.distance_squared(photon.point))
+                .filter(|&distance_squared| distance_squared < 0.25)
+                .collect();
+            expected.sort_by(f64::total_cmp);
+            expected.truncate(20);
+
+            let found: Vec<f64> = map
+                .nearest(point, 20, 0.5)
+                .iter()
+                .map(|&(distance_squared, _)| distance_squared)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn photons_carry_the_irradiance_of_the_lights() {
+        let scene = vec![floor()];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, 1.0, 0.0),
+            Color::ONE,
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+        let mut photon_mapper = PhotonMapper::new(200_000, 1000, 0.5);
+        photon_mapper.preprocess(&world);
+
+        // the diffuse floor reflects albedo / pi of its irradiance, from 1 right under the light.
+        // The photons found are close enough for the irradiance to barely change over their disk
+        let (mut estimate, mut expected) = (0.0, 0.0);
+        for x in [-0.6, -0.3, 0.0, 0.3, 0.6] {
+            let ray = Ray::from_to((x, 1.0, 1.0), (x, 0.0, 0.0));
+            let hit = world.objects.intersect(ray).unwrap();
+            estimate += photon_mapper.global.estimate(&hit, -ray.dir, 1000, 0.5).x;
+            expected += 0.5 / PI / (1.0 + x * x).powf(1.5);
+        }
+        assert!(
+            (estimate - expected).abs() < 0.1 * expected,
+            "{estimate} {expected}"
+        );
+    }
+
+    #[test]
+    fn glass_focuses_point_lights_into_caustics() {
+        let scene = vec![
+            floor(),
+            Box::new(
+                Sphere::new((0.0, 1.5, 0.0), 1.0)
+                    .with_material(Arc::new(Dielectric::new(Color::ONE, 1.5))),
+            ) as Box<dyn Intersect>,
+        ];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, 10.0, 0.0),
+            Color::splat(100.0),
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+        let mut photon_mapper = PhotonMapper::new(10_000, 50, 0.2).with_caustic_photons(20_000);
+        photon_mapper.preprocess(&world);
+
+        let mean = |integrator: &dyn Integrator, to: (f64, f64, f64)| {
+            (0..200)
+                .map(|_| {
+                    integrator
+                        .radiance(Ray::from_to((3.0, 1.0, 0.0), to), &world)
+                        .x
+                })
+                .sum::<f64>()
+                / 200.0
+        };
+
+        // paths from the camera can't find a point light through glass, the floor under the
+        // sphere is lit by the light focused by it, brighter than the floor around
+        let beside = mean(&photon_mapper, (2.0, 0.0, 0.0));
+        let under = mean(&photon_mapper, (0.0, 0.0, 0.0));
+        assert!(under > 10.0 * beside, "{under} {beside}");
+        assert!(mean(&PathTracer::new(8), (0.0, 0.0, 0.0)) < 0.1 * beside);
+    }
+}
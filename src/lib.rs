@@ -1,6 +1,14 @@
 pub mod camera;
+pub mod color;
 pub mod geometry;
+pub mod image;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod medium;
 pub mod object;
+pub mod octree;
+pub mod sampling;
 pub mod scene;
+pub mod texture;
 pub mod tracer;
-pub mod octree;
\ No newline at end of file
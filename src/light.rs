@@ -0,0 +1,699 @@
+use std::{f64::consts::PI, fmt::Debug};
+
+use glam::{DVec2, DVec3};
+
+use nanorand::Rng;
+
+use crate::{
+    color::{luminance, normalized, Color},
+    geometry::{offset_origin, Intersect, Ray},
+    image::load_hdr,
+    sampling::{
+        cosine_hemisphere, orthonormal_basis, uniform_cone, uniform_disk, uniform_sphere,
+        Distribution2D,
+    },
+};
+
+pub mod sky;
+
+/// Light arriving at a point from a sampled point of a light
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    /// direction from the lit point to the light
+    pub dir: DVec3,
+    /// distance from the lit point to the light, infinite for directional lights
+    pub distance: f64,
+    /// normal of the light surface at the sampled point, zero for lights that are not surfaces
+    pub normal: DVec3,
+    /// radiance arriving from `dir`. For delta lights, the irradiance on a surface facing them
+    pub radiance: Color,
+    /// solid angle density of sampling `dir`, 1 for delta lights
+    pub pdf: f64,
+}
+
+/// Ray leaving a light, carrying its light along a path starting at the light
+#[derive(Clone, Copy, Debug)]
+pub struct EmissionSample {
+    pub ray: Ray,
+    /// normal of the light surface at the ray origin, zero for lights that are not surfaces
+    pub normal: DVec3,
+    /// radiance carried by the ray. For delta lights, the intensity or the irradiance
+    pub radiance: Color,
+    /// density of the ray origin per unit area, 1 for point lights
+    pub pdf_pos: f64,
+    /// solid angle density of the ray direction, 1 for directional lights
+    pub pdf_dir: f64,
+}
+
+/// Light source that can be sampled from the points it lights
+pub trait Light: Sync + Send + Debug {
+    /// light arriving at `point` from a random point of the light
+    fn sample(&self, point: DVec3) -> Option<LightSample>;
+
+    /// if the light comes from a single direction, so rays can never hit it by chance
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// radiance arriving along rays going towards `dir` that miss every object
+    fn le(&self, _dir: DVec3) -> Color {
+        Color::ZERO
+    }
+
+    /// solid angle density of `sample` returning `dir` from `point`, for the lights seen by
+    /// rays missing every object
+    fn pdf(&self, _point: DVec3, _dir: DVec3) -> f64 {
+        0.0
+    }
+
+    /// if the light is infinitely far away, like environment maps and directional lights
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /// random ray leaving the light. Infinite lights send them from a disk facing the scene,
+    /// covering the sphere at `scene_center` with `scene_radius`
+    fn sample_emission(&self, _scene_center: DVec3, _scene_radius: f64) -> Option<EmissionSample> {
+        None
+    }
+
+    /// densities of `sample_emission` returning a ray leaving the light towards `dir` from a
+    /// point with `normal`, as (position, direction). Only for lights that are not infinite
+    fn emission_pdf(&self, _normal: DVec3, _dir: DVec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+impl<L: Light + ?Sized> Light for &L {
+    fn sample(&self, point: DVec3) -> Option<LightSample> {
+        (**self).sample(point)
+    }
+
+    fn is_delta(&self) -> bool {
+        (**self).is_delta()
+    }
+
+    fn le(&self, dir: DVec3) -> Color {
+        (**self).le(dir)
+    }
+
+    fn pdf(&self, point: DVec3, dir: DVec3) -> f64 {
+        (**self).pdf(point, dir)
+    }
+
+    fn is_infinite(&self) -> bool {
+        (**self).is_infinite()
+    }
+
+    fn sample_emission(&self, scene_center: DVec3, scene_radius: f64) -> Option<EmissionSample> {
+        (**self).sample_emission(scene_center, scene_radius)
+    }
+
+    fn emission_pdf(&self, normal: DVec3, dir: DVec3) -> (f64, f64) {
+        (**self).emission_pdf(normal, dir)
+    }
+}
+
+/// Light emitted from a single point equally in all directions
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: DVec3,
+    /// radiant intensity, power per unit solid angle
+    pub intensity: Color,
+}
+
+/// Light arriving from a single direction everywhere, like the sun
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// direction the light travels
+    pub dir: DVec3,
+    /// irradiance on a surface facing the light
+    pub irradiance: Color,
+}
+
+/// Point light restricted to a cone, fading out between the inner and outer angles
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: DVec3,
+    /// axis of the cone
+    pub dir: DVec3,
+    /// radiant intensity inside the inner cone
+    pub intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+/// Light arriving from infinitely far away in every direction, from an equirectangular image.
+/// The top row is +y, and the columns go around the y axis starting and ending at -x, with
+/// +z at three quarters of the width
+#[derive(Clone, Debug)]
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    /// radiance, row by row from the top
+    pixels: Vec<Color>,
+    /// pixels sampled proportionally to the power they contribute
+    distribution: Distribution2D,
+}
+
+/// Object with an emissive material, sampled over its surface
+#[derive(Debug)]
+pub struct AreaLight<'a> {
+    object: &'a dyn Intersect,
+    radiance: Color,
+}
+
+impl PointLight {
+    pub fn new(position: DVec3, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+
+    /// light emitting `watts` with the hue of `color` over the whole sphere of directions
+    pub fn with_power(position: DVec3, color: Color, watts: f64) -> Self {
+        PointLight::new(position, normalized(color) * watts / (4.0 * PI))
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: DVec3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+
+        Some(LightSample {
+            dir: to_light / distance,
+            distance,
+            normal: DVec3::ZERO,
+            radiance: self.intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample_emission(&self, _scene_center: DVec3, _scene_radius: f64) -> Option<EmissionSample> {
+        let dir = uniform_sphere();
+        let (pdf_pos, pdf_dir) = self.emission_pdf(DVec3::ZERO, dir);
+
+        Some(EmissionSample {
+            ray: Ray::new(self.position.into(), dir.into()),
+            normal: DVec3::ZERO,
+            radiance: self.intensity,
+            pdf_pos,
+            pdf_dir,
+        })
+    }
+
+    fn emission_pdf(&self, _normal: DVec3, _dir: DVec3) -> (f64, f64) {
+        (1.0, 1.0 / (4.0 * PI))
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(dir: DVec3, irradiance: Color) -> Self {
+        DirectionalLight {
+            dir: dir.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: DVec3) -> Option<LightSample> {
+        Some(LightSample {
+            dir: -self.dir,
+            distance: f64::INFINITY,
+            normal: DVec3::ZERO,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_emission(&self, scene_center: DVec3, scene_radius: f64) -> Option<EmissionSample> {
+        Some(infinite_emission(
+            -self.dir,
+            self.irradiance,
+            1.0,
+            scene_center,
+            scene_radius,
+        ))
+    }
+}
+
+impl SpotLight {
+    /// spot at `position` pointing to `dir`, with full intensity up to `inner_angle` from the
+    /// axis and none past `outer_angle`, both in radians
+    pub fn new(
+        position: DVec3,
+        dir: DVec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        SpotLight {
+            position,
+            dir: dir.normalize(),
+            intensity,
+            cos_inner: inner_angle.min(outer_angle).cos(),
+            cos_outer: outer_angle.cos(),
+        }
+    }
+
+    /// spot emitting `watts` with the hue of `color`, counting half of the fading region
+    pub fn with_power(
+        position: DVec3,
+        dir: DVec3,
+        color: Color,
+        watts: f64,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let spot = SpotLight::new(position, dir, Color::ONE, inner_angle, outer_angle);
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (spot.cos_inner + spot.cos_outer));
+        SpotLight {
+            intensity: normalized(color) * watts / solid_angle,
+            ..spot
+        }
+    }
+
+    /// fraction of the intensity emitted towards `dir`, smoothly fading between the cones
+    fn falloff(&self, dir: DVec3) -> f64 {
+        let cos = self.dir.dot(dir);
+        if cos >= self.cos_inner {
+            return 1.0;
+        }
+
+        let t = ((cos - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: DVec3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let dir = to_light / distance;
+
+        let falloff = self.falloff(-dir);
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            dir,
+            distance,
+            normal: DVec3::ZERO,
+            radiance: falloff * self.intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn sample_emission(&self, _scene_center: DVec3, _scene_radius: f64) -> Option<EmissionSample> {
+        let dir = uniform_cone(self.dir, self.cos_outer);
+        let (pdf_pos, pdf_dir) = self.emission_pdf(DVec3::ZERO, dir);
+
+        Some(EmissionSample {
+            ray: Ray::new(self.position.into(), dir.into()),
+            normal: DVec3::ZERO,
+            radiance: self.falloff(dir) * self.intensity,
+            pdf_pos,
+            pdf_dir,
+        })
+    }
+
+    fn emission_pdf(&self, _normal: DVec3, dir: DVec3) -> (f64, f64) {
+        if self.dir.dot(dir) < self.cos_outer {
+            return (1.0, 0.0);
+        }
+
+        (1.0, 1.0 / (2.0 * PI * (1.0 - self.cos_outer)))
+    }
+}
+
+impl EnvironmentLight {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+
+        // rows near the poles cover less solid angle
+        let weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                luminance(*pixel).max(0.0) * theta.sin()
+            })
+            .collect();
+
+        EnvironmentLight {
+            width,
+            height,
+            distribution: Distribution2D::new(&weights, width, height),
+            pixels,
+        }
+    }
+
+    /// environment from a Radiance HDR file, with its radiance multiplied by `scale`
+    pub fn load_hdr(path: &str, scale: f64) -> Self {
+        let (pixels, width, height) = load_hdr(path);
+        EnvironmentLight::new(width, height, pixels.iter().map(|p| scale * *p).collect())
+    }
+
+    /// position in the image, in [0, 1)^2
+    fn image_coords(dir: DVec3) -> DVec2 {
+        DVec2::new(
+            (dir.z.atan2(dir.x) + PI) / (2.0 * PI),
+            // more precise than acos(y) close to the poles
+            DVec2::new(dir.x, dir.z).length().atan2(dir.y) / PI,
+        )
+    }
+
+    fn dir(coords: DVec2) -> DVec3 {
+        let phi = 2.0 * PI * coords.x - PI;
+        let theta = PI * coords.y;
+        DVec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn radiance(&self, coords: DVec2) -> Color {
+        let x = ((coords.x * self.width as f64) as usize).min(self.width - 1);
+        let y = ((coords.y * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x]
+    }
+
+    /// image density converted to solid angle
+    fn solid_angle_pdf(&self, coords: DVec2, image_pdf: f64) -> f64 {
+        let sin_theta = (PI * coords.y).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        image_pdf / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _point: DVec3) -> Option<LightSample> {
+        let (coords, image_pdf) = self.distribution.sample();
+        let pdf = self.solid_angle_pdf(coords, image_pdf);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            dir: EnvironmentLight::dir(coords),
+            distance: f64::INFINITY,
+            normal: DVec3::ZERO,
+            radiance: self.radiance(coords),
+            pdf,
+        })
+    }
+
+    fn le(&self, dir: DVec3) -> Color {
+        self.radiance(EnvironmentLight::image_coords(dir))
+    }
+
+    fn pdf(&self, _point: DVec3, dir: DVec3) -> f64 {
+        let coords = EnvironmentLight::image_coords(dir);
+        self.solid_angle_pdf(coords, self.distribution.pdf(coords))
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_emission(&self, scene_center: DVec3, scene_radius: f64) -> Option<EmissionSample> {
+        let sample = self.sample(scene_center)?;
+        Some(infinite_emission(
+            sample.dir,
+            sample.radiance,
+            sample.pdf,
+            scene_center,
+            scene_radius,
+        ))
+    }
+}
+
+impl<'a> AreaLight<'a> {
+    /// None if the object does not emit light
+    pub fn new(object: &'a dyn Intersect) -> Option<Self> {
+        let radiance = object.material()?.emitted();
+        if radiance.max_element() <= 0.0 {
+            return None;
+        }
+
+        Some(AreaLight { object, radiance })
+    }
+}
+
+impl Light for AreaLight<'_> {
+    fn sample(&self, point: DVec3) -> Option<LightSample> {
+        let surface = self.object.sample(point)?;
+        let to_light = surface.point - point;
+        let distance = to_light.length();
+
+        Some(LightSample {
+            dir: to_light / distance,
+            distance,
+            normal: surface.normal,
+            radiance: self.radiance,
+            pdf: surface.pdf,
+        })
+    }
+
+    fn sample_emission(&self, _scene_center: DVec3, _scene_radius: f64) -> Option<EmissionSample> {
+        let (point, normal) = self.object.sample_point()?;
+        // both sides of the surface emit
+        let side = if nanorand::tls_rng().generate::<f64>() < 0.5 {
+            normal
+        } else {
+            -normal
+        };
+        let dir = cosine_hemisphere(side);
+        let (pdf_pos, pdf_dir) = self.emission_pdf(normal, dir);
+
+        Some(EmissionSample {
+            ray: Ray::new(offset_origin(point, side, dir).into(), dir.into()),
+            normal,
+            radiance: self.radiance,
+            pdf_pos,
+            pdf_dir,
+        })
+    }
+
+    fn emission_pdf(&self, normal: DVec3, dir: DVec3) -> (f64, f64) {
+        (1.0 / self.object.area(), normal.dot(dir).abs() / (2.0 * PI))
+    }
+}
+
+/// ray arriving at the scene from the direction `dir` of an infinite light, leaving a random
+/// point of a disk facing the scene that covers its bounding sphere
+fn infinite_emission(
+    dir: DVec3,
+    radiance: Color,
+    pdf_dir: f64,
+    scene_center: DVec3,
+    scene_radius: f64,
+) -> EmissionSample {
+    let (tangent, bitangent) = orthonormal_basis(dir);
+    let disk = uniform_disk();
+    let origin = scene_center + scene_radius * (dir + disk.x * tangent + disk.y * bitangent);
+
+    EmissionSample {
+        ray: Ray::new(origin.into(), (-dir).into()),
+        normal: DVec3::ZERO,
+        radiance,
+        pdf_pos: 1.0 / (PI * scene_radius * scene_radius),
+        pdf_dir,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use glam::DVec3;
+
+    use crate::{
+        color::{blackbody, luminance, Color},
+        geometry::Intersect,
+        light::{AreaLight, DirectionalLight, EnvironmentLight, Light, PointLight, SpotLight},
+        material::Emissive,
+        object::{sphere::Sphere, triangle::Triangle},
+    };
+
+    #[test]
+    fn only_emissive_objects_are_lights() {
+        let plain = Sphere::new((0.0, 0.0, 0.0), 1.0);
+        let lamp =
+            Sphere::new((0.0, 0.0, 0.0), 1.0).with_material(Arc::new(Emissive::new(Color::ONE)));
+
+        assert!(AreaLight::new(&plain).is_none());
+        assert!(AreaLight::new(&lamp).is_some());
+    }
+
+    #[test]
+    fn sampled_solid_angle_matches_the_light() {
+        // the pdf integrates to one over the solid angle covered by the light, so the mean
+        // of 1 / pdf over the samples estimates that solid angle
+        let n = 20_000;
+        let estimate = |light: &dyn Light| {
+            (0..n)
+                .map(|_| 1.0 / light.sample(DVec3::ZERO).unwrap().pdf)
+                .sum::<f64>()
+                / n as f64
+        };
+
+        // sphere of radius 1 at distance 2 covers 2 pi (1 - cos(30 degrees))
+        let sphere =
+            Sphere::new((0.0, 0.0, 2.0), 1.0).with_material(Arc::new(Emissive::new(Color::ONE)));
+        let expected = 2.0 * std::f64::consts::PI * (1.0 - 30.0f64.to_radians().cos());
+        assert!((estimate(&AreaLight::new(&sphere).unwrap()) - expected).abs() < 1e-9);
+
+        // an eighth of a face of the unit cube, seen from the center of the cube: 4 pi / 48
+        let triangle = Triangle::from_tuples((0.0, 0.0, 0.5), (0.5, 0.0, 0.5), (0.5, 0.5, 0.5))
+            .with_material(Arc::new(Emissive::new(Color::ONE)));
+        let expected = 4.0 * std::f64::consts::PI / 48.0;
+        let estimated = estimate(&AreaLight::new(&triangle).unwrap());
+        assert!(
+            (estimated - expected).abs() < 0.02 * expected,
+            "{estimated}"
+        );
+    }
+
+    #[test]
+    fn sample_pdf_matches_the_samples() {
+        let objects: [Box<dyn Intersect>; 3] = [
+            // seen from outside and from inside
+            Box::new(Sphere::new((0.0, 0.0, 3.0), 1.0)),
+            Box::new(Sphere::new((0.0, 0.0, 0.5), 1.0)),
+            Box::new(Triangle::from_tuples(
+                (-1.0, 0.0, 2.0),
+                (1.0, 0.0, 3.0),
+                (0.0, 1.0, 2.0),
+            )),
+        ];
+
+        for object in objects {
+            for _ in 0..100 {
+                let sample = object.sample(DVec3::ZERO).unwrap();
+                let pdf = object.sample_pdf(DVec3::ZERO, sample.point);
+                assert!((pdf - sample.pdf).abs() < 1e-9 * pdf, "{object:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn point_light_falls_off_with_the_square_distance() {
+        let light = PointLight::new(DVec3::new(0.0, 2.0, 0.0), Color::splat(8.0));
+
+        let sample = light.sample(DVec3::ZERO).unwrap();
+
+        assert!(sample.dir.abs_diff_eq(DVec3::Y, 1e-9));
+        assert_eq!(sample.distance, 2.0);
+        assert!(sample.radiance.abs_diff_eq(Color::splat(2.0), 1e-9));
+        assert!(light.is_delta());
+    }
+
+    #[test]
+    fn directional_light_is_the_same_everywhere() {
+        let light = DirectionalLight::new(DVec3::new(0.0, -2.0, 0.0), Color::ONE);
+
+        for point in [DVec3::ZERO, DVec3::new(100.0, -3.0, 7.0)] {
+            let sample = light.sample(point).unwrap();
+            assert!(sample.dir.abs_diff_eq(DVec3::Y, 1e-9));
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_eq!(sample.radiance, Color::ONE);
+        }
+    }
+
+    #[test]
+    fn spot_light_fades_between_its_cones() {
+        let light = SpotLight::new(
+            DVec3::new(0.0, 1.0, 0.0),
+            -DVec3::Y,
+            Color::ONE,
+            20.0f64.to_radians(),
+            40.0f64.to_radians(),
+        );
+        let radiance_at = |angle: f64| {
+            let point = DVec3::new(angle.to_radians().tan(), 0.0, 0.0);
+            let distance2 = point.distance_squared(light.position);
+            light
+                .sample(point)
+                .map_or(0.0, |sample| sample.radiance.x * distance2)
+        };
+
+        assert!((radiance_at(0.0) - 1.0).abs() < 1e-9);
+        assert!((radiance_at(15.0) - 1.0).abs() < 1e-9);
+        assert!(radiance_at(25.0) < 1.0 && radiance_at(25.0) > radiance_at(35.0));
+        assert!(radiance_at(35.0) > 0.0);
+        assert_eq!(radiance_at(45.0), 0.0);
+    }
+
+    #[test]
+    fn power_is_spread_over_the_emitted_directions() {
+        let watts = 100.0;
+
+        let point = PointLight::with_power(DVec3::ZERO, blackbody(2700.0), watts);
+        assert!((luminance(point.intensity) * 4.0 * PI - watts).abs() < 1e-9);
+
+        // a hard edged spot covers the solid angle of its cone
+        let angle = 30.0f64.to_radians();
+        let spot = SpotLight::with_power(DVec3::ZERO, DVec3::Y, Color::ONE, watts, angle, angle);
+        let solid_angle = 2.0 * PI * (1.0 - angle.cos());
+        assert!((luminance(spot.intensity) * solid_angle - watts).abs() < 1e-9);
+    }
+
+    #[test]
+    fn environment_directions_match_the_image() {
+        // dark left half towards -z, bright right half towards +z
+        let env = EnvironmentLight::new(2, 1, vec![Color::ZERO, Color::ONE]);
+
+        assert_eq!(env.le(DVec3::new(0.1, 0.2, 1.0)), Color::ONE);
+        assert_eq!(env.le(DVec3::new(0.1, 0.2, -1.0)), Color::ZERO);
+
+        for _ in 0..100 {
+            let sample = env.sample(DVec3::ZERO).unwrap();
+            assert_eq!(sample.radiance, Color::ONE);
+            assert!(sample.dir.z > -1e-9);
+            assert!((env.pdf(DVec3::ZERO, sample.dir) - sample.pdf).abs() < 1e-9 * sample.pdf);
+        }
+    }
+
+    #[test]
+    fn environment_sampling_covers_the_sphere() {
+        // the mean of radiance / pdf over the samples estimates the integral of the radiance
+        // over the sphere, 4 pi for a uniform environment
+        let env = EnvironmentLight::new(4, 3, vec![Color::ONE; 12]);
+        let n = 10_000;
+
+        let integral = (0..n)
+            .map(|_| {
+                let sample = env.sample(DVec3::ZERO).unwrap();
+                sample.radiance.x / sample.pdf
+            })
+            .sum::<f64>()
+            / n as f64;
+
+        assert!((integral - 4.0 * PI).abs() < 0.05 * 4.0 * PI, "{integral}");
+    }
+}
@@ -0,0 +1,312 @@
+use std::f64::consts::PI;
+
+use glam::{DVec2, DVec3};
+use nanorand::Rng;
+
+use crate::{
+    color::{blackbody, luminance, xyz_to_rgb, Color},
+    light::{infinite_emission, EmissionSample, EnvironmentLight, Light, LightSample},
+    sampling::uniform_cone,
+};
+
+/// angular radius of the sun disc, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.00465;
+/// luminance of the sun outside the atmosphere, in kcd/m^2
+const SUN_LUMINANCE: f64 = 1.6e6;
+/// size of the equirectangular table the sky is sampled from
+const TABLE_WIDTH: usize = 256;
+const TABLE_HEIGHT: usize = 128;
+
+/// Clear daylight sky of Preetham et al., "A Practical Analytic Model for Daylight", with the
+/// sun disc. Radiances are in kcd/m^2 times the scale, and nothing comes from below the horizon
+#[derive(Debug)]
+pub struct SunSky {
+    /// direction towards the center of the sun
+    sun_dir: DVec3,
+    /// radiance of the sun disc, after crossing the atmosphere
+    sun_radiance: Color,
+    /// luminance and x, y chromaticity at the zenith
+    zenith: [f64; 3],
+    /// coefficients A to E of the Perez distribution of the luminance and chromaticity
+    perez: [[f64; 5]; 3],
+    /// the sky without the sun, only used to sample directions
+    table: EnvironmentLight,
+    /// probability of sampling the sun disc rather than the sky
+    sun_probability: f64,
+    scale: f64,
+}
+
+impl SunSky {
+    /// sky with the sun `elevation` above the horizon and `azimuth` around the y axis from +x
+    /// towards +z, both in radians. `turbidity` goes from 2 for a clear sky to 10 for haze
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let elevation = elevation.clamp(0.0, PI / 2.0);
+        let theta_s = PI / 2.0 - elevation;
+        let sun_dir = DVec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let chromaticity = |coefficients: [[f64; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let poly = |c: [f64; 4]| (0..4).map(|i| c[i] * thetas[i]).sum::<f64>();
+            t * t * poly(coefficients[0]) + t * poly(coefficients[1]) + poly(coefficients[2])
+        };
+        let zenith = [
+            zenith_luminance,
+            chromaticity([
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ]),
+            chromaticity([
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ]),
+        ];
+
+        let mut sky = SunSky {
+            sun_dir,
+            sun_radiance: SUN_LUMINANCE * blackbody(5778.0) * sun_transmittance(theta_s, t),
+            zenith,
+            perez,
+            table: EnvironmentLight::new(1, 1, vec![Color::ZERO]),
+            sun_probability: 0.0,
+            scale: 1.0,
+        };
+
+        let pixels: Vec<Color> = (0..TABLE_WIDTH * TABLE_HEIGHT)
+            .map(|i| {
+                let coords = DVec2::new(
+                    ((i % TABLE_WIDTH) as f64 + 0.5) / TABLE_WIDTH as f64,
+                    ((i / TABLE_WIDTH) as f64 + 0.5) / TABLE_HEIGHT as f64,
+                );
+                sky.sky_radiance(EnvironmentLight::dir(coords))
+            })
+            .collect();
+
+        // power of the sun and of the sky, as luminance integrated over their solid angles
+        let pixel_solid_angle = 2.0 * PI * PI / (TABLE_WIDTH * TABLE_HEIGHT) as f64;
+        let sky_power: f64 = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let theta = PI * ((i / TABLE_WIDTH) as f64 + 0.5) / TABLE_HEIGHT as f64;
+                luminance(*pixel) * theta.sin() * pixel_solid_angle
+            })
+            .sum();
+        let sun_power = luminance(sky.sun_radiance) * sun_solid_angle();
+
+        sky.table = EnvironmentLight::new(TABLE_WIDTH, TABLE_HEIGHT, pixels);
+        sky.sun_probability = if sky_power > 0.0 {
+            sun_power / (sun_power + sky_power)
+        } else {
+            1.0
+        };
+        sky
+    }
+
+    /// multiplies the radiance of the sun and the sky by `scale`, to bring them to the
+    /// brightness of the scene
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// radiance of the sky towards `dir`, without the sun disc
+    fn sky_radiance(&self, dir: DVec3) -> Color {
+        let cos_theta = dir.y;
+        if cos_theta <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let cos_gamma = dir.dot(self.sun_dir).clamp(-1.0, 1.0);
+        let cos_theta_s = self.sun_dir.y;
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez(self.perez[i], cos_theta, cos_gamma)
+                / perez(self.perez[i], 1.0, cos_theta_s)
+        });
+        if y <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let xyz = DVec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        xyz_to_rgb(xyz).max(Color::ZERO)
+    }
+
+    fn in_sun_disc(&self, dir: DVec3) -> bool {
+        dir.dot(self.sun_dir) >= SUN_ANGULAR_RADIUS.cos()
+    }
+}
+
+impl Light for SunSky {
+    fn sample(&self, point: DVec3) -> Option<LightSample> {
+        let dir = if nanorand::tls_rng().generate::<f64>() < self.sun_probability {
+            uniform_cone(self.sun_dir, SUN_ANGULAR_RADIUS.cos())
+        } else {
+            self.table.sample(point)?.dir
+        };
+
+        let pdf = self.pdf(point, dir);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            dir,
+            distance: f64::INFINITY,
+            normal: DVec3::ZERO,
+            radiance: self.le(dir),
+            pdf,
+        })
+    }
+
+    fn le(&self, dir: DVec3) -> Color {
+        if dir.y <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let sun = if self.in_sun_disc(dir) {
+            self.sun_radiance
+        } else {
+            Color::ZERO
+        };
+        self.scale * (self.sky_radiance(dir) + sun)
+    }
+
+    fn pdf(&self, point: DVec3, dir: DVec3) -> f64 {
+        let sun_pdf = if self.in_sun_disc(dir) {
+            1.0 / sun_solid_angle()
+        } else {
+            0.0
+        };
+        self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * self.table.pdf(point, dir)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn sample_emission(&self, scene_center: DVec3, scene_radius: f64) -> Option<EmissionSample> {
+        let sample = self.sample(scene_center)?;
+        Some(infinite_emission(
+            sample.dir,
+            sample.radiance,
+            sample.pdf,
+            scene_center,
+            scene_radius,
+        ))
+    }
+}
+
+/// relative distribution of the Perez model with the coefficients A to E, at the angle theta
+/// from the zenith and gamma from the sun
+fn perez([a, b, c, d, e]: [f64; 5], cos_theta: f64, cos_gamma: f64) -> f64 {
+    (1.0 + a * (b / cos_theta).exp())
+        * (1.0 + c * (d * cos_gamma.acos()).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn sun_solid_angle() -> f64 {
+    2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos())
+}
+
+/// fraction of the sunlight at `theta_s` from the zenith left after Rayleigh and aerosol
+/// scattering in the atmosphere, at wavelengths close to the sRGB primaries
+fn sun_transmittance(theta_s: f64, turbidity: f64) -> Color {
+    let relative_air_mass =
+        1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = |micrometers: f64| {
+        let rayleigh = -0.008735 * micrometers.powf(-4.08);
+        let aerosol = -beta * micrometers.powf(-1.3);
+        ((rayleigh + aerosol) * relative_air_mass).exp()
+    };
+
+    Color::new(
+        transmittance(0.61),
+        transmittance(0.55),
+        transmittance(0.465),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use glam::DVec3;
+
+    use crate::{
+        color::Color,
+        light::{sky::SunSky, Light},
+    };
+
+    #[test]
+    fn sky_is_blue_with_a_bright_sun() {
+        let sky = SunSky::new(45.0f64.to_radians(), 0.0, 3.0);
+        let sun_dir = DVec3::new(1.0, 1.0, 0.0).normalize();
+
+        let zenith = sky.le(DVec3::Y);
+        assert!(zenith.z > zenith.x, "{zenith}");
+        assert!(sky.le(sun_dir).min_element() > 1000.0 * zenith.max_element());
+        assert_eq!(sky.le(-DVec3::Y), Color::ZERO);
+
+        // the sky brightens towards the sun
+        let opposite = sky.le(DVec3::new(-1.0, 1.0, 0.0).normalize());
+        let near_sun = sky.le(DVec3::new(1.0, 0.8, 0.0).normalize());
+        assert!(near_sun.y > opposite.y);
+
+        // sunsets are red
+        let sunset = SunSky::new(1.0f64.to_radians(), 0.0, 3.0).sun_radiance;
+        assert!(sunset.x > sunset.y && sunset.y > sunset.z, "{sunset}");
+    }
+
+    #[test]
+    fn samples_cover_the_upper_hemisphere() {
+        let sky = SunSky::new(30.0f64.to_radians(), 1.0, 4.0);
+
+        // as for area lights, the mean of 1 / pdf estimates the solid angle of the light
+        let n = 50_000;
+        let mut solid_angle = 0.0;
+        for _ in 0..n {
+            let sample = sky.sample(DVec3::ZERO).unwrap();
+            assert!((sample.pdf - sky.pdf(DVec3::ZERO, sample.dir)).abs() < 1e-9 * sample.pdf);
+            assert_eq!(sample.radiance, sky.le(sample.dir));
+            solid_angle += 1.0 / sample.pdf;
+        }
+        solid_angle /= n as f64;
+
+        assert!(
+            (solid_angle - 2.0 * PI).abs() < 0.05 * 2.0 * PI,
+            "{solid_angle}"
+        );
+    }
+}
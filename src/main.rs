@@ -1,13 +1,37 @@
-use std::{fs::File, io::BufWriter};
-
-use ray_tracer::{scene, tracer};
+use ray_tracer::{
+    color::Color,
+    image::{save_to_png, PixelFormat},
+    integrator::{
+        bidirectional::Bidirectional, photon::PhotonMapper, AmbientOcclusion, DebugView,
+        Integrator, PathTracer, Whitted,
+    },
+    scene, tracer,
+};
 
 fn main() {
     env_logger::init();
 
     let mut movie_scene = scene::scene_from_obj_file();
 
-    let max_reflections = 5;
+    // paths end by Russian roulette, this only caps their length
+    let max_reflections = 64;
+    let mut integrator: Box<dyn Integrator> = match std::env::args().nth(1).as_deref() {
+        None | Some("path") => Box::new(PathTracer::new(max_reflections)),
+        // every vertex of the camera path is joined to every vertex of the light path, so these
+        // paths are kept shorter
+        Some("bdpt") => Box::new(Bidirectional::new(16)),
+        Some("photon") => Box::new(PhotonMapper::new(200_000, 100, 0.1)),
+        Some("whitted") => Box::new(Whitted::new(max_reflections)),
+        Some("ao") => Box::new(AmbientOcclusion::new(1.0, 16)),
+        Some("normal") => Box::new(DebugView::Normal),
+        Some("depth") => Box::new(DebugView::Depth { far: 20.0 }),
+        Some("uv") => Box::new(DebugView::Uv),
+        Some("barycentric") => Box::new(DebugView::Barycentric),
+        Some(other) => panic!(
+            "unknown integrator {}, expected path, bdpt, photon, whitted, ao, normal, depth, uv or barycentric",
+            other
+        ),
+    };
     let samples_per_pixel = 256;
     let num_threads = num_cpus::get();
     let gamma_correction = 1.0 / 2.0;
@@ -16,7 +40,7 @@ fn main() {
     for frame in 0..movie_scene.n_frames {
         movie_scene.calc_frame(frame);
 
-        let mut image = vec![0.0; x_res * y_res];
+        let mut image = vec![Color::ZERO; x_res * y_res];
 
         tracer::render(
             &movie_scene.scene,
@@ -24,7 +48,7 @@ fn main() {
             y_res,
             num_threads,
             samples_per_pixel,
-            max_reflections,
+            integrator.as_mut(),
             &mut image,
         );
 
@@ -34,24 +58,9 @@ fn main() {
             x_res,
             y_res,
             gamma_correction,
+            PixelFormat::Rgb,
         );
 
         println!("Frame {} completed", frame);
     }
 }
-
-fn save_to_png(name: &str, image: &[f64], x_res: usize, y_res: usize, gamma_correction: f64) {
-    let writer = BufWriter::new(File::create(name).unwrap());
-
-    let mut encoder = png::Encoder::new(writer, x_res as u32, y_res as u32);
-    encoder.set_color(png::ColorType::Grayscale);
-    encoder.set_depth(png::BitDepth::Eight);
-
-    let mut data_writer = encoder.write_header().unwrap();
-
-    let data: Vec<u8> = image
-        .iter()
-        .map(|gray| (256.0 * gray.powf(gamma_correction)) as u8)
-        .collect();
-    data_writer.write_image_data(&data).unwrap();
-}
@@ -0,0 +1,927 @@
+use std::{
+    f64::consts::PI,
+    fmt::Debug,
+    sync::{Arc, OnceLock},
+};
+
+use glam::{DVec2, DVec3};
+use nanorand::Rng;
+
+use crate::{
+    color::{normalized, Color},
+    geometry::{face_forward, reflect, refract, Hit, Intersect, Ray},
+    medium::Medium,
+    sampling::{cosine_hemisphere, orthonormal_basis},
+    texture::Texture,
+};
+
+/// Decides how light is scattered at a surface hit
+pub trait Material: Sync + Send + Debug {
+    /// if the ray is not absorbed, return the scattered ray and its attenuation
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter>;
+
+    /// radiance emitted by the surface
+    fn emitted(&self) -> Color {
+        Color::ZERO
+    }
+
+    /// fraction of the light arriving from `wi` that leaves towards `wo`, per unit solid angle
+    /// and including the cosine of `wi` with the normal. Both directions point away from the
+    /// surface. Materials that only scatter specularly can't be evaluated and return zero
+    fn eval(&self, _hit: &Hit, _wo: DVec3, _wi: DVec3) -> Color {
+        Color::ZERO
+    }
+
+    /// solid angle density of `scatter` choosing `wi` for light leaving towards `wo`
+    fn pdf(&self, _hit: &Hit, _wo: DVec3, _wi: DVec3) -> f64 {
+        0.0
+    }
+
+    /// medium filling the object, for invisible surfaces that only bound a volume
+    fn medium(&self) -> Option<&Medium> {
+        None
+    }
+
+    /// every ray a purely specular material scatters the ray into, like both the reflection and
+    /// the refraction of glass, with the fraction of the light each carries. Empty for
+    /// materials that also scatter diffusely
+    fn specular_scatters(&self, _ray: Ray, _hit: &Hit) -> Vec<Scatter> {
+        Vec::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Scatter {
+    pub ray: Ray,
+    pub attenuation: Color,
+    /// if the direction comes from a delta distribution, like a mirror reflection, which
+    /// light sampling can't reproduce
+    pub specular: bool,
+}
+
+/// Lambertian surface, scattering light equally in all directions
+#[derive(Clone, Debug)]
+pub struct Diffuse {
+    /// fraction of the incoming light that is reflected, per channel
+    pub albedo: Arc<dyn Texture>,
+}
+
+/// Perfectly smooth reflective surface
+#[derive(Clone, Copy, Debug)]
+pub struct Mirror {
+    /// fraction of the incoming light that is reflected, per channel
+    pub albedo: Color,
+}
+
+/// Metal/roughness material: GGX microfacet distribution with Smith shadowing and Schlick
+/// Fresnel over a Lambertian base for the non metallic part
+#[derive(Clone, Debug)]
+pub struct Microfacet {
+    /// albedo of dielectrics, specular color of metals
+    pub base_color: Arc<dyn Texture>,
+    /// perceptual roughness, from 0 (smooth) to 1
+    pub roughness: Arc<dyn Texture>,
+    /// from 0 (dielectric) to 1 (metal)
+    pub metallic: Arc<dyn Texture>,
+}
+
+/// Transparent material like glass or water, reflecting and refracting light
+#[derive(Clone, Copy, Debug)]
+pub struct Dielectric {
+    pub color: Color,
+    /// index of refraction
+    pub ior: f64,
+}
+
+/// Light source that does not scatter
+#[derive(Clone, Copy, Debug)]
+pub struct Emissive {
+    pub color: Color,
+}
+
+/// Invisible surface of a closed object filled with a medium, like a cloud of smoke. Rays go
+/// straight through it
+#[derive(Clone, Copy, Debug)]
+pub struct MediumBoundary {
+    pub medium: Medium,
+}
+
+/// Texture perturbing the shading normal
+#[derive(Clone, Debug)]
+pub enum NormalMap {
+    /// tangent space normals encoded as colors, with z along the surface normal
+    Tangent(Arc<dyn Texture>),
+    /// grayscale heights, in world units after scaling by the factor
+    Bump(Arc<dyn Texture>, f64),
+}
+
+/// Material shaded with the normal given by a normal map
+#[derive(Clone, Debug)]
+pub struct NormalMapped {
+    pub material: Arc<dyn Material>,
+    pub normal_map: NormalMap,
+}
+
+/// Light gray diffuse material shared by objects created without one
+pub fn default_material() -> Arc<dyn Material> {
+    static DEFAULT: OnceLock<Arc<dyn Material>> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| Arc::new(Diffuse::new(Color::splat(0.8))))
+        .clone()
+}
+
+impl Diffuse {
+    pub fn new(albedo: Color) -> Self {
+        Diffuse::textured(Arc::new(albedo))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Diffuse { albedo }
+    }
+}
+
+impl Material for Diffuse {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        let wo = -ray.dir;
+        let wi = cosine_hemisphere(face_forward(hit.normal, ray.dir));
+
+        let pdf = self.pdf(hit, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        // the cosine cancels out with the pdf, leaving just the albedo
+        Some(Scatter {
+            ray: hit.spawn_ray(wi),
+            attenuation: self.eval(hit, wo, wi) / pdf,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> Color {
+        let normal = face_forward(hit.normal, -wo);
+        if normal.dot(wo) <= 0.0 || normal.dot(wi) <= 0.0 {
+            return Color::ZERO;
+        }
+
+        self.albedo.value(hit.uv, hit.point) / PI * normal.dot(wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> f64 {
+        face_forward(hit.normal, -wo).dot(wi).max(0.0) / PI
+    }
+}
+
+impl Mirror {
+    pub fn new(albedo: Color) -> Self {
+        Mirror { albedo }
+    }
+}
+
+impl Material for Mirror {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        let normal = face_forward(hit.normal, ray.dir);
+        Some(Scatter {
+            ray: hit.spawn_ray(reflect(ray.dir, normal)),
+            attenuation: self.albedo,
+            specular: true,
+        })
+    }
+
+    fn specular_scatters(&self, ray: Ray, hit: &Hit) -> Vec<Scatter> {
+        self.scatter(ray, hit).into_iter().collect()
+    }
+}
+
+/// reflectance at normal incidence of the non metallic part
+const DIELECTRIC_F0: f64 = 0.04;
+
+/// lower bound for the GGX alpha, avoiding numerical issues on smooth surfaces
+const MIN_ALPHA: f64 = 1e-3;
+
+impl Microfacet {
+    pub fn new(base_color: Color, roughness: f64, metallic: f64) -> Self {
+        Microfacet::textured(
+            Arc::new(base_color),
+            Arc::new(Color::splat(roughness)),
+            Arc::new(Color::splat(metallic)),
+        )
+    }
+
+    /// material with every parameter driven by a texture. Roughness and metallic are read from
+    /// the first channel
+    pub fn textured(
+        base_color: Arc<dyn Texture>,
+        roughness: Arc<dyn Texture>,
+        metallic: Arc<dyn Texture>,
+    ) -> Self {
+        Microfacet {
+            base_color,
+            roughness,
+            metallic,
+        }
+    }
+
+    pub fn metal(base_color: Color, roughness: f64) -> Self {
+        Microfacet::new(base_color, roughness, 1.0)
+    }
+
+    pub fn plastic(base_color: Color, roughness: f64) -> Self {
+        Microfacet::new(base_color, roughness, 0.0)
+    }
+
+    fn alpha(&self, hit: &Hit) -> f64 {
+        let roughness = self.roughness.scalar(hit.uv, hit.point).clamp(0.0, 1.0);
+        (roughness * roughness).max(MIN_ALPHA)
+    }
+
+    fn metallic(&self, hit: &Hit) -> f64 {
+        self.metallic.scalar(hit.uv, hit.point).clamp(0.0, 1.0)
+    }
+
+    /// probability of sampling the specular lobe instead of the diffuse one
+    fn specular_probability(&self, hit: &Hit) -> f64 {
+        0.5 + 0.5 * self.metallic(hit)
+    }
+
+    /// BSDF for light arriving from `wi` and leaving towards `wo`, both pointing away from the
+    /// surface with `normal`
+    fn bsdf(&self, hit: &Hit, wo: DVec3, wi: DVec3, normal: DVec3) -> Color {
+        let cos_o = normal.dot(wo);
+        let cos_i = normal.dot(wi);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let half = (wo + wi).normalize();
+        let alpha = self.alpha(hit);
+        let metallic = self.metallic(hit);
+
+        let base_color = self.base_color.value(hit.uv, hit.point);
+        let f0 = Color::splat(DIELECTRIC_F0).lerp(base_color, metallic);
+        let fresnel = fresnel_schlick(f0, wi.dot(half));
+        let specular = ggx_distribution(normal.dot(half), alpha)
+            * smith_g1(cos_o, alpha)
+            * smith_g1(cos_i, alpha)
+            * fresnel
+            / (4.0 * cos_o * cos_i);
+
+        let diffuse = (1.0 - metallic) * (Color::ONE - fresnel) * base_color / PI;
+
+        specular + diffuse
+    }
+
+    /// solid angle density of sampling `wi` given `wo`, around `normal`
+    fn density(&self, hit: &Hit, wo: DVec3, wi: DVec3, normal: DVec3) -> f64 {
+        let cos_i = normal.dot(wi);
+        if normal.dot(wo) <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+
+        let half = (wo + wi).normalize();
+        let cos_h = normal.dot(half);
+        let specular_pdf =
+            ggx_distribution(cos_h, self.alpha(hit)) * cos_h / (4.0 * wo.dot(half).abs());
+        let p_specular = self.specular_probability(hit);
+
+        p_specular * specular_pdf + (1.0 - p_specular) * cos_i / PI
+    }
+}
+
+impl Material for Microfacet {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        let normal = face_forward(hit.normal, ray.dir);
+        let wo = -ray.dir;
+
+        let wi = if nanorand::tls_rng().generate::<f64>() < self.specular_probability(hit) {
+            reflect(ray.dir, ggx_half_vector(normal, self.alpha(hit)))
+        } else {
+            cosine_hemisphere(normal)
+        };
+
+        let cos_i = normal.dot(wi);
+        let pdf = self.density(hit, wo, wi, normal);
+        if cos_i <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Scatter {
+            ray: hit.spawn_ray(wi),
+            attenuation: self.bsdf(hit, wo, wi, normal) * cos_i / pdf,
+            specular: false,
+        })
+    }
+
+    fn eval(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> Color {
+        let normal = face_forward(hit.normal, -wo);
+        self.bsdf(hit, wo, wi, normal) * normal.dot(wi).max(0.0)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> f64 {
+        self.density(hit, wo, wi, face_forward(hit.normal, -wo))
+    }
+}
+
+/// GGX (Trowbridge-Reitz) normal distribution
+fn ggx_distribution(cos_h: f64, alpha: f64) -> f64 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+
+    let alpha2 = alpha * alpha;
+    let d = cos_h * cos_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+/// Smith masking for a single direction under GGX
+fn smith_g1(cos: f64, alpha: f64) -> f64 {
+    let alpha2 = alpha * alpha;
+    2.0 * cos / (cos + (alpha2 + (1.0 - alpha2) * cos * cos).sqrt())
+}
+
+fn fresnel_schlick(f0: Color, cos: f64) -> Color {
+    f0 + (Color::ONE - f0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+/// microfacet normal around `normal` sampled proportionally to D(h) cos(theta_h)
+fn ggx_half_vector(normal: DVec3, alpha: f64) -> DVec3 {
+    let mut rng = nanorand::tls_rng();
+    let u = rng.generate::<f64>();
+    let phi = 2.0 * PI * rng.generate::<f64>();
+
+    let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    (sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * normal)
+        .normalize()
+}
+
+impl Dielectric {
+    pub fn new(color: Color, ior: f64) -> Self {
+        Dielectric { color, ior }
+    }
+
+    pub fn glass() -> Self {
+        Dielectric::new(Color::ONE, 1.5)
+    }
+}
+
+impl Dielectric {
+    /// reflected direction, refracted one unless reflected totally, and the fraction of the
+    /// light that is reflected
+    fn split(&self, ray: Ray, hit: &Hit) -> (DVec3, Option<DVec3>, f64) {
+        let normal = face_forward(hit.normal, ray.dir);
+        // rays hitting the front face go into the object
+        let eta = if hit.front_face {
+            1.0 / self.ior
+        } else {
+            self.ior
+        };
+        let cos_i = normal.dot(-ray.dir);
+
+        (
+            reflect(ray.dir, normal),
+            refract(ray.dir, normal, eta),
+            fresnel_dielectric(cos_i, eta),
+        )
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        let (reflected, refracted, reflectance) = self.split(ray, hit);
+
+        let refracted = refracted.filter(|_| nanorand::tls_rng().generate::<f64>() >= reflectance);
+
+        Some(Scatter {
+            ray: hit.spawn_ray(refracted.unwrap_or(reflected)),
+            attenuation: self.color,
+            specular: true,
+        })
+    }
+
+    fn specular_scatters(&self, ray: Ray, hit: &Hit) -> Vec<Scatter> {
+        let (reflected, refracted, reflectance) = self.split(ray, hit);
+
+        let scatter = |dir: DVec3, fraction: f64| Scatter {
+            ray: hit.spawn_ray(dir),
+            attenuation: fraction * self.color,
+            specular: true,
+        };
+        match refracted {
+            Some(refracted) => vec![
+                scatter(reflected, reflectance),
+                scatter(refracted, 1.0 - reflectance),
+            ],
+            None => vec![scatter(reflected, 1.0)],
+        }
+    }
+}
+
+/// fraction of unpolarized light reflected by a dielectric interface, where `eta` is the ratio
+/// of the refraction indices (incident over transmitted)
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_perpendicular = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+impl Emissive {
+    pub fn new(color: Color) -> Self {
+        Emissive { color }
+    }
+
+    /// material for the surfaces of `emitters`, which emit `watts` in total from both of their
+    /// sides, with the hue of `color`
+    pub fn with_power(color: Color, watts: f64, emitters: &[&dyn Intersect]) -> Self {
+        let area: f64 = emitters.iter().map(|emitter| emitter.area()).sum();
+        assert!(area > 0.0, "emitters without area can't emit power");
+        Emissive::new(normalized(color) * watts / (2.0 * PI * area))
+    }
+}
+
+impl Material for Emissive {
+    fn scatter(&self, _ray: Ray, _hit: &Hit) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self) -> Color {
+        self.color
+    }
+}
+
+impl MediumBoundary {
+    pub fn new(medium: Medium) -> Self {
+        MediumBoundary { medium }
+    }
+}
+
+impl Material for MediumBoundary {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        Some(Scatter {
+            ray: hit.spawn_ray(ray.dir),
+            attenuation: Color::ONE,
+            specular: true,
+        })
+    }
+
+    fn medium(&self) -> Option<&Medium> {
+        Some(&self.medium)
+    }
+
+    fn specular_scatters(&self, ray: Ray, hit: &Hit) -> Vec<Scatter> {
+        self.scatter(ray, hit).into_iter().collect()
+    }
+}
+
+/// UV step of the finite differences taken on bump maps
+const BUMP_DELTA: f64 = 0.0005;
+
+impl NormalMap {
+    /// shading normal at the hit, on the same side of the surface as the hit normal
+    pub fn perturb(&self, hit: &Hit) -> DVec3 {
+        let normal = match self {
+            NormalMap::Tangent(texture) => {
+                let tangent = (hit.dpdu - hit.normal * hit.normal.dot(hit.dpdu)).normalize();
+                let mut bitangent = hit.normal.cross(tangent);
+                // mirrored UVs flip the v axis
+                if bitangent.dot(hit.dpdv) < 0.0 {
+                    bitangent = -bitangent;
+                }
+
+                let local = 2.0 * texture.value(hit.uv, hit.point) - 1.0;
+                local.x * tangent + local.y * bitangent + local.z * hit.normal
+            }
+            NormalMap::Bump(texture, scale) => {
+                let height = |du: f64, dv: f64| {
+                    scale
+                        * texture.scalar(
+                            hit.uv + DVec2::new(du, dv),
+                            hit.point + du * hit.dpdu + dv * hit.dpdv,
+                        )
+                };
+                let center = height(0.0, 0.0);
+                let dhdu = (height(BUMP_DELTA, 0.0) - center) / BUMP_DELTA;
+                let dhdv = (height(0.0, BUMP_DELTA) - center) / BUMP_DELTA;
+
+                // derivatives of the surface displaced along the normal by the height
+                (hit.dpdu + dhdu * hit.normal).cross(hit.dpdv + dhdv * hit.normal)
+            }
+        };
+
+        face_forward(normal.normalize(), -hit.normal)
+    }
+}
+
+impl NormalMapped {
+    pub fn new(material: Arc<dyn Material>, normal_map: NormalMap) -> Self {
+        NormalMapped {
+            material,
+            normal_map,
+        }
+    }
+
+    /// the hit with the mapped normal
+    fn shading_hit<'a>(&self, hit: &Hit<'a>) -> Hit<'a> {
+        Hit {
+            normal: self.normal_map.perturb(hit),
+            ..*hit
+        }
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+        self.material.scatter(ray, &self.shading_hit(hit))
+    }
+
+    fn emitted(&self) -> Color {
+        self.material.emitted()
+    }
+
+    fn eval(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> Color {
+        self.material.eval(&self.shading_hit(hit), wo, wi)
+    }
+
+    fn pdf(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> f64 {
+        self.material.pdf(&self.shading_hit(hit), wo, wi)
+    }
+
+    fn medium(&self) -> Option<&Medium> {
+        self.material.medium()
+    }
+
+    fn specular_scatters(&self, ray: Ray, hit: &Hit) -> Vec<Scatter> {
+        self.material.specular_scatters(ray, &self.shading_hit(hit))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use glam::{DVec2, DVec3};
+    use nanorand::Rng;
+
+    use crate::{
+        color::{luminance, Color},
+        geometry::{AABBox, Hit, Intersect, Ray},
+        light::{AreaLight, Light},
+        material::{
+            default_material, fresnel_dielectric, Dielectric, Diffuse, Emissive, Material,
+            MediumBoundary, Microfacet, Mirror, NormalMap, NormalMapped,
+        },
+        medium::Medium,
+        object::triangle::Triangle,
+        texture::{
+            procedural::{Gradient, Space},
+            ImageTexture,
+        },
+    };
+
+    /// stand-in for the object hit, which materials don't look at
+    #[derive(Debug)]
+    struct NoObject;
+
+    impl Intersect for NoObject {
+        fn intersect(&self, _ray: Ray) -> Option<Hit<'_>> {
+            None
+        }
+
+        fn bounds(&self) -> AABBox {
+            AABBox::default()
+        }
+    }
+
+    /// hit at the origin of a surface facing +y
+    fn hit_at_origin(material: &dyn Material) -> Hit<'_> {
+        Hit {
+            t: 1.0,
+            point: DVec3::ZERO,
+            normal: DVec3::Y,
+            geometric_normal: DVec3::Y,
+            front_face: true,
+            uv: DVec2::ZERO,
+            dpdu: DVec3::X,
+            dpdv: -DVec3::Z,
+            barycentric: DVec3::ZERO,
+            material,
+            object: &NoObject,
+            primitive: 0,
+        }
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        // ((n1 - n2) / (n1 + n2))^2 for air to glass
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fresnel_is_total_past_critical_angle() {
+        assert_eq!(fresnel_dielectric(60.0f64.to_radians().cos(), 1.5), 1.0);
+    }
+
+    #[test]
+    fn glass_reflects_internally_at_grazing_angles() {
+        let glass = Dielectric::glass();
+        // coming from inside the object, against the outward normal
+        let hit = Hit {
+            front_face: false,
+            ..hit_at_origin(&glass)
+        };
+        let ray = Ray::new((0.0, -1.0, 0.0), (2.0, 1.0, 0.0));
+
+        for _ in 0..32 {
+            let scatter = glass.scatter(ray, &hit).unwrap();
+            assert!(scatter.ray.dir.y < 0.0);
+            assert!(scatter.ray.origin.y < 0.0);
+        }
+    }
+
+    #[test]
+    fn glass_refracts_or_reflects_to_the_right_side() {
+        let glass = Dielectric::glass();
+        let hit = hit_at_origin(&glass);
+        let ray = Ray::new((0.0, 1.0, 0.0), (0.0, -1.0, 0.0));
+
+        for _ in 0..32 {
+            let scatter = glass.scatter(ray, &hit).unwrap();
+            // refracted rays keep going down and start below the surface, reflected go back up
+            assert_eq!(scatter.ray.dir.y < 0.0, scatter.ray.origin.y < 0.0);
+        }
+    }
+
+    #[test]
+    fn diffuse_scatters_with_cosine_distribution() {
+        let n = 100_000;
+        let diffuse = Diffuse::new(Color::splat(0.5));
+        let hit = hit_at_origin(&diffuse);
+        let ray = Ray::from_to((-1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+
+        let mut mean_cos = 0.0;
+        for _ in 0..n {
+            let scatter = diffuse.scatter(ray, &hit).unwrap();
+            assert!(scatter.attenuation.abs_diff_eq(Color::splat(0.5), 1e-9));
+            mean_cos += scatter.ray.dir.y / n as f64;
+        }
+
+        // E[cos(theta)] for the density cos(theta) / pi
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn diffuse_albedo_comes_from_the_texture() {
+        let texture = ImageTexture::new(2, 1, vec![Color::X, Color::Y]);
+        let diffuse = Diffuse::textured(Arc::new(texture));
+        let hit = Hit {
+            uv: DVec2::new(0.75, 0.5),
+            ..hit_at_origin(&diffuse)
+        };
+        let ray = Ray::from_to((-1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+
+        let scatter = diffuse.scatter(ray, &hit).unwrap();
+
+        assert!(scatter.attenuation.abs_diff_eq(Color::Y, 1e-9));
+    }
+
+    #[test]
+    fn diffuse_scatters_to_the_side_of_the_ray() {
+        let diffuse = Diffuse::new(Color::ONE);
+        let hit = hit_at_origin(&diffuse);
+        // coming from below the surface
+        let ray = Ray::from_to((-1.0, -1.0, 0.0), (0.0, 0.0, 0.0));
+
+        for _ in 0..32 {
+            let scatter = diffuse.scatter(ray, &hit).unwrap();
+            assert!(scatter.ray.dir.y < 0.0);
+            assert!(scatter.ray.origin.y < 0.0);
+        }
+    }
+
+    #[test]
+    fn mirror_reflects_around_the_normal() {
+        let mirror = Mirror::new(Color::ONE);
+        let hit = hit_at_origin(&mirror);
+        let ray = Ray::from_to((-1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+
+        let scatter = mirror.scatter(ray, &hit).unwrap();
+
+        assert!(scatter
+            .ray
+            .dir
+            .abs_diff_eq(DVec3::new(1.0, 1.0, 0.0).normalize(), 1e-9));
+        assert!(scatter.ray.origin.y > 0.0);
+    }
+
+    #[test]
+    fn smooth_metal_reflects_like_a_mirror() {
+        let metal = Microfacet::metal(Color::ONE, 0.0);
+        let hit = hit_at_origin(&metal);
+        let wo = DVec3::new(-1.0, 1.0, 0.0).normalize();
+        let mirror = DVec3::new(1.0, 1.0, 0.0).normalize();
+        // 0.1 radians away from the mirror direction
+        let tilted = PI / 4.0 - 0.1;
+        let off_mirror = DVec3::new(tilted.cos(), tilted.sin(), 0.0);
+
+        // samples around the mirror direction carry all the light
+        let weight = metal.eval(&hit, wo, mirror) / metal.pdf(&hit, wo, mirror);
+        assert!(weight.abs_diff_eq(Color::ONE, 1e-2), "{weight}");
+
+        // directions elsewhere are almost never sampled and reflect almost nothing
+        assert!(metal.pdf(&hit, wo, off_mirror) < 1e-6 * metal.pdf(&hit, wo, mirror));
+        assert!(
+            metal.eval(&hit, wo, off_mirror).max_element()
+                < 1e-6 * metal.eval(&hit, wo, mirror).max_element()
+        );
+    }
+
+    #[test]
+    fn microfacet_sampling_matches_its_bsdf() {
+        let n = 200_000;
+        let mut rng = nanorand::tls_rng();
+        let wo = DVec3::new(1.0, 2.0, 0.0).normalize();
+        let ray = Ray::new((1.0, 2.0, 0.0), (-1.0, -2.0, 0.0));
+
+        for (roughness, metallic) in [(0.4, 1.0), (0.7, 1.0), (0.5, 0.0), (1.0, 0.5)] {
+            let material = Microfacet::new(Color::ONE, roughness, metallic);
+            let hit = hit_at_origin(&material);
+
+            let importance_sampled = (0..n)
+                .filter_map(|_| material.scatter(ray, &hit))
+                .inspect(|scatter| assert!(scatter.ray.dir.y > 0.0))
+                .map(|scatter| scatter.attenuation.x)
+                .sum::<f64>()
+                / n as f64;
+
+            // reflectance integrated with uniform hemisphere samples
+            let uniform = (0..n)
+                .map(|_| {
+                    let cos = rng.generate::<f64>();
+                    let phi = 2.0 * PI * rng.generate::<f64>();
+                    let sin = (1.0 - cos * cos).sqrt();
+                    let wi = DVec3::new(sin * phi.cos(), cos, sin * phi.sin());
+                    material.eval(&hit, wo, wi).x * 2.0 * PI
+                })
+                .sum::<f64>()
+                / n as f64;
+
+            let params = format!("roughness {roughness} metallic {metallic}");
+            assert!(importance_sampled <= 1.01, "{params}");
+            assert!((importance_sampled - uniform).abs() < 0.02, "{params}");
+        }
+    }
+
+    #[test]
+    fn roughness_can_come_from_a_texture() {
+        // smooth on the left half of the UV space, rough on the right
+        let roughness = ImageTexture::new(2, 1, vec![Color::ZERO, Color::ONE]);
+        let material = Microfacet::textured(
+            Arc::new(Color::ONE),
+            Arc::new(roughness),
+            Arc::new(Color::ONE),
+        );
+        let ray = Ray::from_to((-1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+        let mirror_dir = DVec3::new(1.0, 1.0, 0.0).normalize();
+
+        let smooth = Hit {
+            uv: DVec2::new(0.25, 0.5),
+            ..hit_at_origin(&material)
+        };
+        let rough = Hit {
+            uv: DVec2::new(0.75, 0.5),
+            ..hit_at_origin(&material)
+        };
+
+        // median distance from the mirror direction, ignoring the rare samples in the tails
+        let spread = |hit: &Hit| {
+            let mut distances: Vec<f64> = (0..101)
+                .filter_map(|_| material.scatter(ray, hit))
+                .map(|scatter| 1.0 - scatter.ray.dir.dot(mirror_dir))
+                .collect();
+            distances.sort_by(f64::total_cmp);
+            distances[distances.len() / 2]
+        };
+
+        assert!(spread(&smooth) < 1e-4);
+        assert!(spread(&rough) > 1e-2);
+    }
+
+    #[test]
+    fn emissive_power_is_spread_over_its_area() {
+        let watts = 60.0;
+        let [a, b] = Triangle::quad(
+            DVec3::ZERO,
+            DVec3::X,
+            DVec3::new(1.0, 2.0, 0.0),
+            2.0 * DVec3::Y,
+            default_material(),
+        );
+        let bulb = Arc::new(Emissive::with_power(
+            Color::new(1.0, 0.8, 0.6),
+            watts,
+            &[&a, &b],
+        ));
+        let panel = [a, b].map(|triangle| triangle.with_material(bulb.clone()));
+
+        // power of the rays the lights emit, out of both sides
+        let samples = 1000;
+        let power: f64 = panel
+            .iter()
+            .map(|triangle| {
+                let light = AreaLight::new(triangle).unwrap();
+                (0..samples)
+                    .map(|_| {
+                        let emission = light.sample_emission(DVec3::ZERO, 1.0).unwrap();
+                        luminance(emission.radiance) * emission.normal.dot(emission.ray.dir).abs()
+                            / (emission.pdf_pos * emission.pdf_dir)
+                    })
+                    .sum::<f64>()
+                    / samples as f64
+            })
+            .sum();
+
+        assert!((power - watts).abs() < 1e-6);
+    }
+
+    #[test]
+    fn emissive_absorbs_rays() {
+        let light = Emissive::new(Color::ONE);
+        let hit = hit_at_origin(&light);
+
+        assert!(light
+            .scatter(Ray::from_to((0.0, 1.0, 0.0), (0.0, 0.0, 0.0)), &hit)
+            .is_none());
+        assert_eq!(light.emitted(), Color::ONE);
+    }
+
+    #[test]
+    fn normal_mapped_medium_boundaries_keep_their_medium() {
+        let fog = Medium::fog(Color::ONE, 0.5, 0.0);
+        let boundary = NormalMapped::new(
+            Arc::new(MediumBoundary::new(fog)),
+            NormalMap::Tangent(Arc::new(Color::new(0.5, 0.5, 1.0))),
+        );
+
+        assert_eq!(boundary.medium(), Some(&fog));
+    }
+
+    #[test]
+    fn flat_normal_map_keeps_the_normal() {
+        let material = Diffuse::new(Color::ONE);
+        let hit = hit_at_origin(&material);
+
+        let flat = NormalMap::Tangent(Arc::new(Color::new(0.5, 0.5, 1.0)));
+        assert!(flat.perturb(&hit).abs_diff_eq(DVec3::Y, 1e-9));
+
+        let constant_height = NormalMap::Bump(Arc::new(Color::splat(0.3)), 1.0);
+        assert!(constant_height.perturb(&hit).abs_diff_eq(DVec3::Y, 1e-9));
+    }
+
+    #[test]
+    fn normal_map_is_in_tangent_space() {
+        let material = Diffuse::new(Color::ONE);
+        let hit = hit_at_origin(&material);
+
+        // tilted 45 degrees towards the tangent, which is +x
+        let tilted = NormalMap::Tangent(Arc::new(Color::new(
+            0.5 + 0.5 * 0.5f64.sqrt(),
+            0.5,
+            0.5 + 0.5 * 0.5f64.sqrt(),
+        )));
+
+        assert!(tilted
+            .perturb(&hit)
+            .abs_diff_eq(DVec3::new(1.0, 1.0, 0.0).normalize(), 1e-9));
+    }
+
+    #[test]
+    fn bump_map_tilts_the_normal_downhill() {
+        let material = Diffuse::new(Color::ONE);
+        let hit = hit_at_origin(&material);
+
+        // height grows with u, so along +x, with a slope of 1
+        let ramp = Gradient::new(
+            Color::splat(-1.0),
+            Color::splat(1.0),
+            DVec3::new(-1.0, 0.0, 0.0),
+            DVec3::new(1.0, 0.0, 0.0),
+            Space::Uv,
+        );
+        let bump = NormalMap::Bump(Arc::new(ramp), 1.0);
+
+        assert!(bump
+            .perturb(&hit)
+            .abs_diff_eq(DVec3::new(-1.0, 1.0, 0.0).normalize(), 1e-6));
+    }
+}
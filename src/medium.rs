@@ -0,0 +1,196 @@
+use std::f64::consts::PI;
+
+use glam::DVec3;
+use nanorand::Rng;
+
+use crate::{color::Color, sampling::orthonormal_basis};
+
+/// Homogeneous participating medium like fog or smoke, absorbing and scattering the light going
+/// through it equally everywhere. Only the path tracer renders media, the other integrators
+/// ignore them and see through their boundaries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    /// fraction of the light absorbed per unit length, per channel
+    pub sigma_a: Color,
+    /// fraction of the light scattered to other directions per unit length, per channel
+    pub sigma_s: Color,
+    /// asymmetry of the Henyey-Greenstein phase function, from -1 (back scattering) through 0
+    /// (isotropic) to 1 (forward scattering)
+    pub g: f64,
+}
+
+/// Point a ray going through a medium reaches
+#[derive(Clone, Copy, Debug)]
+pub struct DistanceSample {
+    /// distance to the point the ray scatters at, None if it goes through the whole segment
+    pub scattered_at: Option<f64>,
+    /// transmittance, times the scattering coefficient if the ray scatters, over the density of
+    /// the sample
+    pub weight: Color,
+}
+
+impl Medium {
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        Medium {
+            sigma_a,
+            sigma_s,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// medium scattering `density` of the light per unit length, with the color `albedo` and
+    /// absorbing the rest
+    pub fn fog(albedo: Color, density: f64, g: f64) -> Self {
+        Medium::new(density * (Color::ONE - albedo), density * albedo, g)
+    }
+
+    /// fraction of the light removed per unit length, by absorption or scattering
+    pub fn sigma_t(&self) -> Color {
+        self.sigma_a + self.sigma_s
+    }
+
+    /// fraction of the light left after going `distance` through the medium
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let sigma_t = self.sigma_t();
+        // channels that are not attenuated stay at 1, even for infinite distances
+        let channel = |sigma: f64| {
+            if sigma > 0.0 {
+                (-sigma * distance).exp()
+            } else {
+                1.0
+            }
+        };
+        Color::new(channel(sigma_t.x), channel(sigma_t.y), channel(sigma_t.z))
+    }
+
+    /// point a ray going through the medium for `max_distance` scatters at, if any. The
+    /// distance is sampled proportionally to the transmittance of a random channel
+    pub fn sample_distance(&self, max_distance: f64) -> DistanceSample {
+        // media that only absorb just dim the light
+        if self.sigma_s == Color::ZERO {
+            return DistanceSample {
+                scattered_at: None,
+                weight: self.transmittance(max_distance),
+            };
+        }
+
+        let sigma_t = self.sigma_t();
+        let mut rng = nanorand::tls_rng();
+        let sigma = sigma_t[rng.generate_range(0..3_usize)];
+        let distance = if sigma > 0.0 {
+            -(1.0 - rng.generate::<f64>()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
+
+        let scattered = distance < max_distance;
+        let transmittance = self.transmittance(distance.min(max_distance));
+        // averaged over the channel choice
+        if scattered {
+            let pdf = (sigma_t * transmittance).dot(Color::ONE) / 3.0;
+            DistanceSample {
+                scattered_at: Some(distance),
+                weight: transmittance * self.sigma_s / pdf,
+            }
+        } else {
+            let pdf = transmittance.dot(Color::ONE) / 3.0;
+            DistanceSample {
+                scattered_at: None,
+                weight: if pdf > 0.0 {
+                    transmittance / pdf
+                } else {
+                    Color::ZERO
+                },
+            }
+        }
+    }
+
+    /// fraction of the light arriving from `wi` scattered towards `wo` per unit solid angle, both
+    /// pointing away from the scattering point
+    pub fn phase(&self, wo: DVec3, wi: DVec3) -> f64 {
+        henyey_greenstein(-wo.dot(wi), self.g)
+    }
+
+    /// direction the light leaving towards `wo` comes from, sampled with a density equal to
+    /// the phase function
+    pub fn sample_phase(&self, wo: DVec3) -> DVec3 {
+        let mut rng = nanorand::tls_rng();
+        let g = self.g;
+        let u = rng.generate::<f64>();
+        // inverse of the cumulative distribution of the cosine with the direction of travel
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.generate::<f64>();
+
+        // light coming from wi travels along -wi, deflected by theta to leave along wo
+        let (tangent, bitangent) = orthonormal_basis(wo);
+        -(sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * wo)
+            .normalize()
+    }
+}
+
+/// density of light being deflected by the angle with cosine `cos_theta`, per unit solid angle
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use glam::DVec3;
+
+    use crate::{color::Color, medium::Medium, sampling::uniform_sphere};
+
+    #[test]
+    fn phase_function_is_normalized_and_sampled_exactly() {
+        let medium = Medium::fog(Color::ONE, 1.0, 0.6);
+        let wo = DVec3::new(0.3, -0.2, 0.9).normalize();
+        let n = 100_000;
+
+        let integral = (0..n)
+            .map(|_| medium.phase(wo, uniform_sphere()) * 4.0 * PI)
+            .sum::<f64>()
+            / n as f64;
+        assert!((integral - 1.0).abs() < 0.03, "{integral}");
+
+        // the mean cosine of the deflection is the asymmetry of Henyey-Greenstein
+        let mean_cos = (0..n)
+            .map(|_| -medium.sample_phase(wo).dot(wo))
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean_cos - 0.6).abs() < 0.01, "{mean_cos}");
+    }
+
+    #[test]
+    fn distance_samples_weigh_up_to_the_transmittance() {
+        let medium = Medium::new(Color::new(0.1, 0.2, 0.4), Color::new(0.5, 0.3, 0.1), 0.0);
+        let max_distance = 2.0;
+        let n = 100_000;
+
+        let (mut through, mut scattered) = (Color::ZERO, Color::ZERO);
+        for _ in 0..n {
+            let sample = medium.sample_distance(max_distance);
+            match sample.scattered_at {
+                Some(distance) => {
+                    assert!(distance < max_distance);
+                    scattered += sample.weight;
+                }
+                None => through += sample.weight,
+            }
+        }
+
+        // the light going through, and the scattering coefficient integrated over the segment
+        let transmittance = medium.transmittance(max_distance);
+        let expected = medium.sigma_s / medium.sigma_t() * (Color::ONE - transmittance);
+        assert!((through / n as f64).abs_diff_eq(transmittance, 1e-2));
+        assert!((scattered / n as f64).abs_diff_eq(expected, 1e-2));
+    }
+}
@@ -1,50 +1,490 @@
 use std::{
+    collections::HashMap,
     fs::File,
     io::{BufReader, Read},
+    path::Path,
+    sync::Arc,
 };
 
-use wavefront_obj::obj::Primitive;
+use glam::{DVec2, DVec3};
+use log::warn;
+use wavefront_obj::obj::{ObjSet, Primitive};
 
-use crate::{geometry::Intersect, object::triangle::Triangle};
+use crate::{
+    geometry::Intersect,
+    material::{default_material, Material},
+    object::triangle::Triangle,
+};
 
+pub mod mtl;
 pub mod sphere;
 pub mod triangle;
 
+/// faces of a smoothing group meeting at a larger angle keep a sharp edge when normals are
+/// generated for them
+const CREASE_ANGLE_DEGREES: f64 = 60.0;
+
+/// where the shading normals of imported triangles come from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    /// the normals of the file, generated for faces of smoothing groups without them
+    FromFile,
+    /// normals generated for every face, ignoring those of the file and its smoothing groups.
+    /// Faces meeting at more than `crease_angle` degrees keep a sharp edge
+    Smooth { crease_angle: f64 },
+}
+
+/// import the triangles of an OBJ file, with the materials of its MTL library. Triangles without
+/// a known material get the default one
 pub fn import_from_wavefront_obj_file(path: &str) -> Vec<Box<dyn Intersect>> {
+    import_from_wavefront_obj_file_with_shading(path, Shading::FromFile)
+}
+
+/// import the triangles of an OBJ file like `import_from_wavefront_obj_file`, with their normals
+/// given by `shading`
+pub fn import_from_wavefront_obj_file_with_shading(
+    path: &str,
+    shading: Shading,
+) -> Vec<Box<dyn Intersect>> {
+    let parsed_obj = parse_obj_file(path);
+
+    let materials = match &parsed_obj.material_library {
+        Some(library) => {
+            let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+            mtl::load_mtl_file(&base_dir.join(library))
+        }
+        None => HashMap::new(),
+    };
+
+    triangles(parsed_obj, shading, |name| {
+        name.and_then(|name| {
+            let material = materials.get(name).cloned();
+            if material.is_none() {
+                warn!("material {} not found in the material library", name);
+            }
+            material
+        })
+        .unwrap_or_else(default_material)
+    })
+}
+
+/// import the triangles of an OBJ file, all of them with `material`, ignoring its MTL library
+pub fn import_from_wavefront_obj_file_with_material(
+    path: &str,
+    material: Arc<dyn Material>,
+) -> Vec<Box<dyn Intersect>> {
+    triangles(parse_obj_file(path), Shading::FromFile, |_| {
+        material.clone()
+    })
+}
+
+fn parse_obj_file(path: &str) -> ObjSet {
     let file = File::open(path).unwrap();
     let mut reader = BufReader::new(file);
     let mut content = String::new();
     reader.read_to_string(&mut content).unwrap();
 
-    let parsed_obj = wavefront_obj::obj::parse(content.as_str()).unwrap();
+    wavefront_obj::obj::parse(content.as_str()).unwrap()
+}
 
+/// triangles of all objects, with the material given by `material_for` the `usemtl` name.
+/// Triangles without normals in the file get them generated from the faces of their smoothing
+/// groups around their vertices, and are flat outside of any group, unless `shading` smooths
+/// every face
+fn triangles(
+    parsed_obj: ObjSet,
+    shading: Shading,
+    material_for: impl Fn(Option<&str>) -> Arc<dyn Material>,
+) -> Vec<Box<dyn Intersect>> {
     let mut triangles: Vec<Box<dyn Intersect>> = Vec::new();
 
     for obj in parsed_obj.objects {
-        for geom in obj.geometry {
-            for shape in geom.shapes {
-                if let Primitive::Triangle(a, b, c, ..) = shape.primitive {
-                    triangles.push(Box::new(Triangle::from_tuples(
-                        (
-                            obj.vertices[a.0].x,
-                            obj.vertices[a.0].y,
-                            obj.vertices[a.0].z,
-                        ),
-                        (
-                            obj.vertices[b.0].x,
-                            obj.vertices[b.0].y,
-                            obj.vertices[b.0].z,
-                        ),
-                        (
-                            obj.vertices[c.0].x,
-                            obj.vertices[c.0].y,
-                            obj.vertices[c.0].z,
-                        ),
-                    )));
+        let positions: Vec<DVec3> = obj
+            .vertices
+            .iter()
+            .map(|v| DVec3::new(v.x, v.y, v.z))
+            .collect();
+
+        let mut faces = Vec::new();
+        for geom in &obj.geometry {
+            let material = material_for(geom.material_name.as_deref());
+            for shape in &geom.shapes {
+                if let Primitive::Triangle(a, b, c) = shape.primitive {
+                    let groups: Vec<u32> = match shading {
+                        // all faces in one group
+                        Shading::Smooth { .. } => vec![1],
+                        // group 0 is the same as `s off`
+                        Shading::FromFile => shape
+                            .smoothing_groups
+                            .iter()
+                            .copied()
+                            .filter(|&group| group != 0)
+                            .collect(),
+                    };
+                    faces.push(([a, b, c], groups, material.clone()));
                 }
             }
         }
+
+        let generated_normals = vertex_normals(
+            &positions,
+            &faces
+                .iter()
+                .map(|(corners, groups, _)| (corners.map(|corner| corner.0), groups.as_slice()))
+                .collect::<Vec<_>>(),
+            match shading {
+                Shading::FromFile => CREASE_ANGLE_DEGREES,
+                Shading::Smooth { crease_angle } => crease_angle,
+            }
+            .to_radians(),
+        );
+
+        for (([a, b, c], _, material), generated_normals) in faces.iter().zip(generated_normals) {
+            let mut triangle = Triangle::new(positions[a.0], positions[b.0], positions[c.0])
+                .with_material(material.clone());
+
+            if let (Some(uv_a), Some(uv_b), Some(uv_c)) = (a.1, b.1, c.1) {
+                let uv =
+                    |index: usize| DVec2::new(obj.tex_vertices[index].u, obj.tex_vertices[index].v);
+                triangle = triangle.with_uvs([uv(uv_a), uv(uv_b), uv(uv_c)]);
+            }
+
+            let file_normals = match shading {
+                Shading::FromFile => (a.2, b.2, c.2),
+                Shading::Smooth { .. } => (None, None, None),
+            };
+            triangle = match (file_normals, generated_normals) {
+                ((Some(n_a), Some(n_b), Some(n_c)), _) => {
+                    let normal = |index: usize| {
+                        DVec3::new(
+                            obj.normals[index].x,
+                            obj.normals[index].y,
+                            obj.normals[index].z,
+                        )
+                    };
+                    triangle.with_normals([normal(n_a), normal(n_b), normal(n_c)])
+                }
+                (_, Some(generated_normals)) => triangle.with_normals(generated_normals),
+                (_, None) => triangle,
+            };
+
+            triangles.push(Box::new(triangle));
+        }
     }
 
     triangles
 }
+
+/// normals at the corners of the triangles `faces`, given as indices into `positions` with their
+/// smoothing groups. Each is the average of the normals of the faces around the vertex that share
+/// a group with the face, weighted by their angle at it. Faces meeting at more than
+/// `crease_angle` are not averaged, so the edge between them stays sharp. None for faces in no
+/// group, which are flat
+fn vertex_normals(
+    positions: &[DVec3],
+    faces: &[([usize; 3], &[u32])],
+    crease_angle: f64,
+) -> Vec<Option<[DVec3; 3]>> {
+    let face_normals: Vec<DVec3> = faces
+        .iter()
+        .map(|&([a, b, c], _)| {
+            (positions[b] - positions[a])
+                .cross(positions[c] - positions[a])
+                .normalize_or_zero()
+        })
+        .collect();
+
+    // angle of each face at each of its corners
+    let corner_angles: Vec<[f64; 3]> = faces
+        .iter()
+        .map(|(face, _)| {
+            [0, 1, 2].map(|corner| {
+                let p = positions[face[corner]];
+                let next = positions[face[(corner + 1) % 3]];
+                let prev = positions[face[(corner + 2) % 3]];
+                let angle = (next - p).angle_between(prev - p);
+                if angle.is_nan() {
+                    0.0
+                } else {
+                    angle
+                }
+            })
+        })
+        .collect();
+
+    // faces around each vertex, with the corner of the face at it
+    let mut faces_at = vec![Vec::new(); positions.len()];
+    for (face, (vertices, _)) in faces.iter().enumerate() {
+        for (corner, &vertex) in vertices.iter().enumerate() {
+            faces_at[vertex].push((face, corner));
+        }
+    }
+
+    let cos_crease = crease_angle.cos();
+    faces
+        .iter()
+        .enumerate()
+        .map(|(face, (vertices, groups))| {
+            if groups.is_empty() {
+                return None;
+            }
+
+            let normal = face_normals[face];
+            let smoothed_with = |other: usize| {
+                faces[other].1.iter().any(|group| groups.contains(group))
+                    && face_normals[other].dot(normal) >= cos_crease
+            };
+            Some([0, 1, 2].map(|corner| {
+                faces_at[vertices[corner]]
+                    .iter()
+                    .filter(|&&(other, _)| smoothed_with(other))
+                    .map(|&(other, other_corner)| {
+                        corner_angles[other][other_corner] * face_normals[other]
+                    })
+                    .fold(DVec3::ZERO, |sum, weighted| sum + weighted)
+                    .try_normalize()
+                    .unwrap_or(normal)
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use glam::{DVec2, DVec3};
+
+    use wavefront_obj::obj::Primitive;
+
+    use crate::{
+        color::Color,
+        geometry::{Intersect, Ray},
+        object::{
+            import_from_wavefront_obj_file, import_from_wavefront_obj_file_with_shading,
+            parse_obj_file, Shading,
+        },
+    };
+
+    #[test]
+    fn import_texture_coordinates() {
+        let path = std::env::temp_dir().join("ray_tracer_import_texture_coordinates.obj");
+        fs::write(
+            &path,
+            "o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vt 0.5 0.0
+vt 0.0 0.5
+f 1/1 2/2 3/3
+",
+        )
+        .unwrap();
+
+        let triangles = import_from_wavefront_obj_file(path.to_str().unwrap());
+
+        assert_eq!(triangles.len(), 1);
+        let hit = triangles[0]
+            .intersect(Ray::from_to((0.5, 0.25, 1.0), (0.5, 0.25, -1.0)))
+            .unwrap();
+        assert!(hit.uv.abs_diff_eq(DVec2::new(0.25, 0.125), 1e-9));
+    }
+
+    #[test]
+    fn import_vertex_normals() {
+        let path = std::env::temp_dir().join("ray_tracer_import_vertex_normals.obj");
+        fs::write(
+            &path,
+            "o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+vn 0.0 0.0 1.0
+vn 1.0 0.0 1.0
+vn 0.0 1.0 1.0
+f 1//1 2//2 3//3
+",
+        )
+        .unwrap();
+
+        let triangles = import_from_wavefront_obj_file(path.to_str().unwrap());
+
+        let hit = triangles[0]
+            .intersect(Ray::from_to((0.5, 0.5, 1.0), (0.5, 0.5, -1.0)))
+            .unwrap();
+        assert!(hit
+            .normal
+            .abs_diff_eq(DVec3::new(1.0, 1.0, 2.0).normalize(), 1e-9));
+        assert!(hit.geometric_normal.abs_diff_eq(DVec3::Z, 1e-9));
+    }
+
+    #[test]
+    fn generated_normals_are_smooth_within_the_crease_angle() {
+        let path = std::env::temp_dir().join("ray_tracer_generated_normals.obj");
+        // a roof folded 30 degrees along the x axis, with a wall 90 degrees down from one side
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        fs::write(
+            &path,
+            format!(
+                "o Roof
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+v 0.0 {} {}
+v 0.0 0.0 -1.0
+s 1
+f 1 2 3
+f 2 1 4
+f 1 3 5
+",
+                -cos, sin
+            ),
+        )
+        .unwrap();
+
+        let triangles = import_from_wavefront_obj_file(path.to_str().unwrap());
+
+        // the fold is shaded smooth, with the face normals averaged at the shared edge
+        let on_the_fold = triangles[0]
+            .intersect(Ray::from_to((1e-6, 1e-6, 1.0), (1e-6, 1e-6, -1.0)))
+            .unwrap();
+        let roof_normal = DVec3::new(0.0, sin, 1.0 + cos).normalize();
+        assert!(on_the_fold.normal.abs_diff_eq(roof_normal, 1e-3));
+
+        // the wall keeps a sharp edge
+        let on_the_wall = triangles[2]
+            .intersect(Ray::from_to((1.0, 1e-6, -1e-6), (-1.0, 1e-6, -1e-6)))
+            .unwrap();
+        assert!(on_the_wall.normal.abs_diff_eq(-DVec3::X, 1e-9));
+    }
+
+    #[test]
+    fn faces_outside_of_smoothing_groups_are_flat() {
+        let path = std::env::temp_dir().join("ray_tracer_flat_faces.obj");
+        // the same fold as above, in no smoothing group and then in two different ones
+        let (sin, cos) = 30f64.to_radians().sin_cos();
+        fs::write(
+            &path,
+            format!(
+                "o Roof
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+v 0.0 {} {}
+s off
+f 1 2 3
+f 2 1 4
+s 1
+f 1 2 3
+s 2
+f 2 1 4
+",
+                -cos, sin
+            ),
+        )
+        .unwrap();
+
+        let triangles = import_from_wavefront_obj_file(path.to_str().unwrap());
+
+        for triangle in [&triangles[0], &triangles[2]] {
+            let on_the_fold = triangle
+                .intersect(Ray::from_to((1e-6, 1e-6, 1.0), (1e-6, 1e-6, -1.0)))
+                .unwrap();
+            assert!(on_the_fold.normal.abs_diff_eq(DVec3::Z, 1e-9));
+        }
+    }
+
+    #[test]
+    fn smooth_shading_varies_the_normal_across_the_faces_of_the_torus() {
+        let obj = parse_obj_file("torus.obj");
+        let (vertices, Primitive::Triangle(a, b, c)) = (
+            &obj.objects[0].vertices,
+            obj.objects[0].geometry[0].shapes[0].primitive,
+        ) else {
+            panic!("the torus starts with a triangle");
+        };
+        let [a, b, c] = [a, b, c].map(|corner| {
+            DVec3::new(
+                vertices[corner.0].x,
+                vertices[corner.0].y,
+                vertices[corner.0].z,
+            )
+        });
+        let face_normal = (b - a).cross(c - a).normalize();
+
+        // normals near each of the corners of the first face
+        let near_corners = |triangles: &[Box<dyn Intersect>]| {
+            [(a, b, c), (b, c, a), (c, a, b)].map(|(corner, p, q)| {
+                let point = 0.8 * corner + 0.1 * p + 0.1 * q;
+                triangles[0]
+                    .intersect(Ray::from_to(
+                        (point + face_normal).into(),
+                        (point - face_normal).into(),
+                    ))
+                    .unwrap()
+                    .normal
+            })
+        };
+
+        let [a_smooth, b_smooth, c_smooth] =
+            near_corners(&import_from_wavefront_obj_file_with_shading(
+                "torus.obj",
+                Shading::Smooth { crease_angle: 60.0 },
+            ));
+        assert!(a_smooth.angle_between(b_smooth) > 1f64.to_radians());
+        assert!(b_smooth.angle_between(c_smooth) > 1f64.to_radians());
+        assert!(c_smooth.angle_between(a_smooth) > 1f64.to_radians());
+
+        // the file has a flat normal per face
+        let [a_flat, b_flat, c_flat] = near_corners(&import_from_wavefront_obj_file("torus.obj"));
+        assert!(a_flat.abs_diff_eq(b_flat, 1e-9));
+        assert!(b_flat.abs_diff_eq(c_flat, 1e-9));
+    }
+
+    #[test]
+    fn import_materials_from_the_material_library() {
+        let dir = std::env::temp_dir().join("ray_tracer_import_materials");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("lamp.mtl"),
+            "newmtl Bulb
+Ke 3.0 3.0 2.5
+newmtl Base
+Kd 0.2 0.2 0.2
+",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lamp.obj"),
+            "mtllib lamp.mtl
+o Lamp
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+v 0.0 0.0 -1.0
+v 1.0 0.0 -1.0
+v 0.0 1.0 -1.0
+usemtl Bulb
+f 1 2 3
+usemtl Base
+f 4 5 6
+",
+        )
+        .unwrap();
+
+        let triangles = import_from_wavefront_obj_file(dir.join("lamp.obj").to_str().unwrap());
+
+        assert_eq!(triangles.len(), 2);
+        let emitted = |triangle: usize, z: f64| {
+            triangles[triangle]
+                .intersect(Ray::from_to((0.25, 0.25, z + 1.0), (0.25, 0.25, z - 1.0)))
+                .unwrap()
+                .material
+                .emitted()
+        };
+        assert_eq!(emitted(0, 0.0), Color::new(3.0, 3.0, 2.5));
+        assert_eq!(emitted(1, -1.0), Color::ZERO);
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{debug, warn};
+
+use crate::{
+    color::Color,
+    material::{Dielectric, Diffuse, Emissive, Material, Microfacet, NormalMap, NormalMapped},
+    texture::{ImageTexture, Texture},
+};
+
+/// Material as described in a Wavefront MTL file
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub name: String,
+    /// Kd
+    pub diffuse: Color,
+    /// Ks
+    pub specular: Color,
+    /// Ns, from 0 to 1000
+    pub specular_exponent: f64,
+    /// Ke
+    pub emissive: Color,
+    /// d, or 1 - Tr. 1 is opaque
+    pub dissolve: f64,
+    /// Ni
+    pub optical_density: f64,
+    /// map_Kd, relative to the MTL file
+    pub diffuse_map: Option<String>,
+    /// map_Bump or bump, relative to the MTL file
+    pub bump_map: Option<String>,
+    /// -bm option of the bump map
+    pub bump_multiplier: f64,
+    /// norm, tangent space normal map relative to the MTL file
+    pub normal_map: Option<String>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            name: String::new(),
+            diffuse: Color::splat(0.8),
+            specular: Color::ZERO,
+            specular_exponent: 0.0,
+            emissive: Color::ZERO,
+            dissolve: 1.0,
+            optical_density: 1.5,
+            diffuse_map: None,
+            bump_map: None,
+            bump_multiplier: 1.0,
+            normal_map: None,
+        }
+    }
+}
+
+/// Parses the materials of an MTL file, ignoring the statements the renderer has no use for
+pub fn parse_mtl(content: &str) -> Vec<MtlMaterial> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+
+    for line in content.lines() {
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.push(MtlMaterial {
+                name: args.join(" "),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(material) = materials.last_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => material.diffuse = parse_color(&args),
+            "Ks" => material.specular = parse_color(&args),
+            "Ke" => material.emissive = parse_color(&args),
+            "Ns" => material.specular_exponent = parse_number(&args, 0.0),
+            "d" => material.dissolve = parse_number(&args, 1.0),
+            "Tr" => material.dissolve = 1.0 - parse_number(&args, 0.0),
+            "Ni" => material.optical_density = parse_number(&args, 1.5),
+            "map_Kd" => material.diffuse_map = map_file(&args),
+            "map_Bump" | "map_bump" | "bump" => {
+                material.bump_map = map_file(&args);
+                material.bump_multiplier = map_option(&args, "-bm").unwrap_or(1.0);
+            }
+            "norm" => material.normal_map = map_file(&args),
+            _ => debug!("ignoring MTL statement {:?}", line),
+        }
+    }
+
+    materials
+}
+
+/// Loads the materials of an MTL file, by name. A missing file gives no materials
+pub fn load_mtl_file(path: &Path) -> HashMap<String, Arc<dyn Material>> {
+    let Ok(content) = fs::read_to_string(path) else {
+        warn!("could not read material library {}", path.display());
+        return HashMap::new();
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut textures = HashMap::new();
+
+    parse_mtl(&content)
+        .into_iter()
+        .map(|mtl| {
+            let material = mtl.to_material(base_dir, &mut textures);
+            (mtl.name, material)
+        })
+        .collect()
+}
+
+/// Phong exponent of a perfectly rough surface as exported by modeling tools
+const MAX_SPECULAR_EXPONENT: f64 = 1000.0;
+
+/// images already loaded, by path and whether they hold data instead of colors. None for the
+/// ones that could not be loaded
+pub type TextureCache = HashMap<(PathBuf, bool), Option<Arc<ImageTexture>>>;
+
+/// None, with a warning once per file, if the file is missing or not a PNG
+fn load_texture(
+    base_dir: &Path,
+    file: &str,
+    data: bool,
+    textures: &mut TextureCache,
+) -> Option<Arc<ImageTexture>> {
+    let path = base_dir.join(file);
+    textures
+        .entry((path.clone(), data))
+        .or_insert_with(|| {
+            let texture = if data {
+                ImageTexture::load_png_data(&path)
+            } else {
+                ImageTexture::load_png(&path)
+            };
+            if texture.is_none() {
+                warn!(
+                    "could not load texture {}, only PNG is supported",
+                    path.display()
+                );
+            }
+            texture.map(Arc::new)
+        })
+        .clone()
+}
+
+impl MtlMaterial {
+    /// Maps the material onto the closest renderer material. Emissive materials become lights,
+    /// transparent ones glass and the rest diffuse or metal/rough, depending on the specular
+    /// color, shaded with the normal or bump map if any. Textures are loaded relative to
+    /// `base_dir` and shared through `textures`. Without its texture, the diffuse color is the
+    /// flat Kd and the normal or bump map is left out
+    pub fn to_material(&self, base_dir: &Path, textures: &mut TextureCache) -> Arc<dyn Material> {
+        let material = self.surface_material(base_dir, textures);
+
+        let normal_map = if let Some(file) = &self.normal_map {
+            load_texture(base_dir, file, true, textures).map(|texture| NormalMap::Tangent(texture))
+        } else if let Some(file) = &self.bump_map {
+            load_texture(base_dir, file, true, textures)
+                .map(|texture| NormalMap::Bump(texture, self.bump_multiplier))
+        } else {
+            None
+        };
+        let Some(normal_map) = normal_map else {
+            return material;
+        };
+
+        Arc::new(NormalMapped::new(material, normal_map))
+    }
+
+    fn surface_material(&self, base_dir: &Path, textures: &mut TextureCache) -> Arc<dyn Material> {
+        if self.emissive.max_element() > 0.0 {
+            return Arc::new(Emissive::new(self.emissive));
+        }
+
+        if self.dissolve < 1.0 {
+            return Arc::new(Dielectric::new(Color::ONE, self.optical_density));
+        }
+
+        let diffuse_map = self
+            .diffuse_map
+            .as_ref()
+            .and_then(|file| load_texture(base_dir, file, false, textures));
+        let diffuse: Arc<dyn Texture> = match &diffuse_map {
+            Some(texture) => texture.clone(),
+            None => Arc::new(self.diffuse),
+        };
+
+        if self.specular.max_element() <= 0.0 {
+            return Arc::new(Diffuse::textured(diffuse));
+        }
+
+        // same conversion used by Blender when exporting the roughness
+        let roughness = 1.0
+            - (self.specular_exponent / MAX_SPECULAR_EXPONENT)
+                .clamp(0.0, 1.0)
+                .sqrt();
+
+        // a black diffuse color with a specular one is how metals are usually described
+        if diffuse_map.is_none() && self.diffuse.max_element() <= 0.0 {
+            Arc::new(Microfacet::metal(self.specular, roughness))
+        } else {
+            Arc::new(Microfacet::textured(
+                diffuse,
+                Arc::new(Color::splat(roughness)),
+                Arc::new(Color::ZERO),
+            ))
+        }
+    }
+}
+
+fn parse_number(args: &[&str], default: f64) -> f64 {
+    args.first()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(default)
+}
+
+fn parse_color(args: &[&str]) -> Color {
+    let channels: Vec<f64> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+    match channels[..] {
+        [gray] => Color::splat(gray),
+        [r, g, b, ..] => Color::new(r, g, b),
+        _ => Color::ZERO,
+    }
+}
+
+/// file name of a texture map statement, which comes after the options
+fn map_file(args: &[&str]) -> Option<String> {
+    args.last().map(|file| file.to_string())
+}
+
+/// value of a single valued texture map option like `-bm 0.5`
+fn map_option(args: &[&str], option: &str) -> Option<f64> {
+    args.iter()
+        .position(|arg| *arg == option)
+        .and_then(|i| args.get(i + 1))
+        .and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::Path};
+
+    use crate::{
+        color::Color,
+        material::Diffuse,
+        object::mtl::{parse_mtl, MtlMaterial},
+    };
+
+    #[test]
+    fn parse_all_supported_statements() {
+        let materials = parse_mtl(
+            "# exported
+newmtl Painted Metal
+Ns 250.0
+Ka 1.0 1.0 1.0
+Kd 0.8 0.1 0.1
+Ks 0.5 0.5 0.5
+Ke 0.0 0.0 0.0
+Ni 1.45
+d 1.0
+illum 2
+map_Kd textures/paint.png
+map_Bump -bm 0.3 textures/paint_normal.png
+
+newmtl Glass
+Tr 0.9
+Ni 1.5
+illum 7
+norm -bm 1.0 glass_normal.png
+",
+        );
+
+        assert_eq!(
+            materials,
+            vec![
+                MtlMaterial {
+                    name: "Painted Metal".to_string(),
+                    diffuse: Color::new(0.8, 0.1, 0.1),
+                    specular: Color::splat(0.5),
+                    specular_exponent: 250.0,
+                    emissive: Color::ZERO,
+                    dissolve: 1.0,
+                    optical_density: 1.45,
+                    diffuse_map: Some("textures/paint.png".to_string()),
+                    bump_map: Some("textures/paint_normal.png".to_string()),
+                    bump_multiplier: 0.3,
+                    normal_map: None,
+                },
+                MtlMaterial {
+                    name: "Glass".to_string(),
+                    dissolve: 1.0 - 0.9,
+                    optical_density: 1.5,
+                    normal_map: Some("glass_normal.png".to_string()),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn materials_are_mapped_to_the_closest_kind() {
+        let to_material = |mtl: MtlMaterial| {
+            format!("{:?}", mtl.to_material(Path::new("."), &mut HashMap::new()))
+        };
+
+        let light = MtlMaterial {
+            emissive: Color::new(5.0, 4.0, 3.0),
+            ..Default::default()
+        };
+        assert!(to_material(light).starts_with("Emissive"));
+
+        let glass = MtlMaterial {
+            dissolve: 0.2,
+            optical_density: 1.33,
+            ..Default::default()
+        };
+        assert!(to_material(glass).starts_with("Dielectric"));
+
+        let matte = MtlMaterial::default();
+        assert!(to_material(matte).starts_with("Diffuse"));
+
+        let gold = MtlMaterial {
+            diffuse: Color::ZERO,
+            specular: Color::new(1.0, 0.8, 0.3),
+            specular_exponent: 900.0,
+            ..Default::default()
+        };
+        assert!(to_material(gold).starts_with("Microfacet"));
+    }
+
+    #[test]
+    fn missing_textures_fall_back_to_the_flat_material() {
+        let painted = MtlMaterial {
+            diffuse: Color::new(0.8, 0.1, 0.1),
+            diffuse_map: Some("missing/paint.jpg".to_string()),
+            normal_map: Some("missing/paint_normal.tga".to_string()),
+            ..Default::default()
+        };
+
+        let material = painted.to_material(Path::new("."), &mut HashMap::new());
+
+        let expected = Diffuse::new(Color::new(0.8, 0.1, 0.1));
+        assert_eq!(format!("{:?}", material), format!("{:?}", expected));
+    }
+}
@@ -1,12 +1,18 @@
-use glam::DVec3;
-use nanorand::Rng;
+use std::{f64::consts::PI, sync::Arc};
 
-use crate::geometry::{AABBox, Intersect, Ray};
+use glam::{DVec2, DVec3};
 
-#[derive(Clone, Copy, Debug)]
+use crate::{
+    geometry::{AABBox, Hit, Intersect, Ray, SurfaceSample},
+    material::{default_material, Material},
+    sampling::{orthonormal_basis, uniform_cone, uniform_sphere},
+};
+
+#[derive(Clone, Debug)]
 pub struct Sphere {
     pub center: DVec3,
     pub radius: f64,
+    pub material: Arc<dyn Material>,
 }
 
 impl Sphere {
@@ -14,55 +20,90 @@ impl Sphere {
         Self {
             center: DVec3::new(x, y, z),
             radius,
+            material: default_material(),
         }
     }
-}
 
-impl Intersect for Sphere {
-    fn intersect(&self, ray: Ray) -> Option<Ray> {
-        fn delta(s: &Sphere, ray: Ray) -> f64 {
-            (2.0 * (ray.dir.dot(ray.origin - s.center))).powi(2)
-                - 4.0 * ((ray.origin - s.center).length_squared() - s.radius * s.radius)
-        }
+    pub fn with_material(self, material: Arc<dyn Material>) -> Self {
+        Self { material, ..self }
+    }
 
-        let delta = delta(self, ray);
+    /// longitude and latitude of the point with `normal`, mapped to [0, 1]. The seam is at -x
+    /// and v grows towards +y
+    fn uv(normal: DVec3) -> DVec2 {
+        DVec2::new(
+            (normal.z.atan2(normal.x) + PI) / (2.0 * PI),
+            1.0 - normal.y.clamp(-1.0, 1.0).acos() / PI,
+        )
+    }
 
-        if delta <= 0.0 {
-            return None;
+    /// cosine of the half angle of the cone of directions to the sphere seen from `origin`.
+    /// None from inside, including points on the surface, for which the cone degenerates
+    fn cone_cos_max(&self, origin: DVec3) -> Option<f64> {
+        let sin2_max = self.radius * self.radius / self.center.distance_squared(origin);
+        (sin2_max < 1.0 / (1.0 + 1e-6)).then(|| (1.0 - sin2_max).sqrt())
+    }
+
+    /// derivatives of the point at `normal` with respect to u and v. The poles, where u is
+    /// undefined, get an arbitrary tangent frame
+    fn derivatives(&self, normal: DVec3) -> (DVec3, DVec3) {
+        let d = self.radius * normal;
+        let rho = (d.x * d.x + d.z * d.z).sqrt();
+
+        if rho < 1e-9 * self.radius {
+            return orthonormal_basis(normal);
         }
 
-        let p = -2.0 * ray.dir.dot(ray.origin - self.center);
-        let q = delta.sqrt();
+        (
+            2.0 * PI * DVec3::new(-d.z, 0.0, d.x),
+            PI * DVec3::new(-d.x * d.y / rho, rho, -d.z * d.y / rho),
+        )
+    }
+}
 
-        let d1 = (p + q) / 2.0;
-        let d2 = (p - q) / 2.0;
+impl Intersect for Sphere {
+    fn intersect(&self, ray: Ray) -> Option<Hit<'_>> {
+        let to_center = self.center - ray.origin;
+        let b = ray.dir.dot(to_center);
+        // the distance from the center to the line of the ray is measured directly rather than
+        // through the difference of two large squares, which loses precision for grazing rays
+        let delta = self.radius * self.radius - (b * ray.dir - to_center).length_squared();
 
-        if d1 < 0.0 && d2 < 0.0 {
+        if delta <= 0.0 {
             return None;
         }
 
-        let d = if d1 > 0.0 && d2 < 0.0 {
-            d1
-        } else if d1 < 0.0 && d2 > 0.0 {
-            d2
+        let q = delta.sqrt();
+
+        // nearest of the two intersections inside the ray interval
+        let near = b - q;
+        let far = b + q;
+        let d = if ray.contains(near) {
+            near
+        } else if ray.contains(far) {
+            far
         } else {
-            d1.min(d2)
+            return None;
         };
 
         let intersect_point = ray.origin + d * ray.dir;
         let normal = (intersect_point - self.center).normalize();
+        let (dpdu, dpdv) = self.derivatives(normal);
 
-        let mut rng = nanorand::tls_rng();
-        let rand = DVec3::new(
-            rng.generate::<f64>() - 0.5,
-            rng.generate::<f64>() - 0.5,
-            rng.generate::<f64>() - 0.5,
-        ) / 16.0;
-
-        Some(Ray::new(
-            (intersect_point + 0.001 * normal).into(),
-            (normal + rand).normalize().into(),
-        ))
+        Some(Hit {
+            t: d,
+            point: intersect_point,
+            normal,
+            geometric_normal: normal,
+            front_face: normal.dot(ray.dir) < 0.0,
+            uv: Sphere::uv(normal),
+            dpdu,
+            dpdv,
+            barycentric: DVec3::ZERO,
+            material: self.material.as_ref(),
+            object: self,
+            primitive: 0,
+        })
     }
 
     fn bounds(&self) -> crate::geometry::AABBox {
@@ -79,6 +120,65 @@ impl Intersect for Sphere {
             ),
         }
     }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.material.as_ref())
+    }
+
+    fn sample(&self, origin: DVec3) -> Option<SurfaceSample> {
+        let to_center = self.center - origin;
+        let dist2 = to_center.length_squared();
+        let radius2 = self.radius * self.radius;
+
+        let Some(cos_max) = self.cone_cos_max(origin) else {
+            // from inside the whole surface is visible, sample it by area
+            let (point, normal) = self.sample_point()?;
+            let pdf = self.sample_pdf(origin, point);
+            return (pdf > 0.0).then_some(SurfaceSample { point, normal, pdf });
+        };
+
+        // from outside sample the cone of directions subtended by the sphere
+        let dir = uniform_cone(to_center / dist2.sqrt(), cos_max);
+
+        // nearest intersection along dir, grazing directions touch the silhouette
+        let b = dir.dot(to_center);
+        let t = b
+            - (radius2 - (b * dir - to_center).length_squared())
+                .max(0.0)
+                .sqrt();
+        let point = origin + t * dir;
+
+        Some(SurfaceSample {
+            point,
+            normal: (point - self.center).normalize(),
+            pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+        })
+    }
+
+    fn sample_pdf(&self, origin: DVec3, point: DVec3) -> f64 {
+        if let Some(cos_max) = self.cone_cos_max(origin) {
+            return 1.0 / (2.0 * PI * (1.0 - cos_max));
+        }
+
+        // area density converted to solid angle
+        let to_point = point - origin;
+        let normal = (point - self.center).normalize();
+        let cos = normal.dot(to_point.normalize()).abs();
+        if cos > 0.0 {
+            to_point.length_squared() / (cos * self.area())
+        } else {
+            0.0
+        }
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_point(&self) -> Option<(DVec3, DVec3)> {
+        let normal = uniform_sphere();
+        Some((self.center + self.radius * normal, normal))
+    }
 }
 
 #[test]
@@ -95,3 +195,65 @@ fn test_intersect() {
     assert!(obj.intersect(ray2).is_none());
     assert!(obj.intersect(ray3).is_some());
 }
+
+#[test]
+fn test_uv() {
+    let obj = Sphere::new((0.0, 0.0, 0.0), 1.0);
+
+    let top = obj
+        .intersect(Ray::from_to((0.0, 5.0, 0.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    assert!((top.uv.y - 1.0).abs() < 1e-9);
+
+    let equator = obj
+        .intersect(Ray::from_to((5.0, 0.0, 0.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    assert!(equator.uv.abs_diff_eq(DVec2::new(0.5, 0.5), 1e-9));
+
+    let quarter = obj
+        .intersect(Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    assert!(quarter.uv.abs_diff_eq(DVec2::new(0.75, 0.5), 1e-9));
+}
+
+#[test]
+fn test_derivatives() {
+    let obj = Sphere::new((0.0, 0.0, 0.0), 2.0);
+
+    let equator = obj
+        .intersect(Ray::from_to((5.0, 0.0, 0.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    // a full turn in u and half a turn in v
+    assert!(equator
+        .dpdu
+        .abs_diff_eq(DVec3::new(0.0, 0.0, 4.0 * PI), 1e-9));
+    assert!(equator
+        .dpdv
+        .abs_diff_eq(DVec3::new(0.0, 2.0 * PI, 0.0), 1e-9));
+
+    let pole = obj
+        .intersect(Ray::from_to((0.0, 5.0, 0.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    assert!(pole.dpdu.dot(pole.normal).abs() < 1e-9);
+    assert!(pole.dpdv.dot(pole.normal).abs() < 1e-9);
+    assert!(pole.dpdu.cross(pole.dpdv).length() > 0.5);
+}
+
+#[test]
+fn hits_from_inside_are_on_the_back_face() {
+    let obj = Sphere::new((0.0, 0.0, 0.0), 2.0);
+
+    let outside = obj
+        .intersect(Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0)))
+        .unwrap();
+    assert!((outside.t - 3.0).abs() < 1e-9);
+    assert!(outside.front_face);
+
+    let inside = obj
+        .intersect(Ray::from_to((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)))
+        .unwrap();
+    assert!((inside.t - 2.0).abs() < 1e-9);
+    assert!(!inside.front_face);
+    // normals keep pointing out of the sphere
+    assert!(inside.geometric_normal.abs_diff_eq(DVec3::Z, 1e-9));
+}
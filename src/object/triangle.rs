@@ -1,27 +1,95 @@
-use glam::DVec3;
+use std::sync::Arc;
+
+use glam::{DVec2, DVec3};
 use log::debug;
-use nanorand::Rng;
 
-use crate::geometry::{AABBox, Intersect, Ray};
+use crate::{
+    geometry::{face_forward, AABBox, Hit, Intersect, Ray, SurfaceSample},
+    material::{default_material, Material},
+    sampling::{orthonormal_basis, uniform_triangle},
+};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Triangle {
     pub a: DVec3,
     pub b: DVec3,
     pub c: DVec3,
+    /// texture coordinates of a, b and c
+    pub uvs: [DVec2; 3],
+    /// shading normals at a, b and c, the face is flat without them
+    pub normals: Option<[DVec3; 3]>,
+    pub material: Arc<dyn Material>,
     normal: DVec3,
+    /// derivatives of the position with respect to the texture coordinates
+    dpdu: DVec3,
+    dpdv: DVec3,
 }
 
 impl Triangle {
     pub fn new(a: DVec3, b: DVec3, c: DVec3) -> Self {
+        let uvs = [DVec2::ZERO, DVec2::X, DVec2::Y];
+        let normal = (b - a).cross(c - a).normalize();
+        let (dpdu, dpdv) = Triangle::derivatives([a, b, c], uvs, normal);
         Triangle {
             a,
             b,
             c,
-            normal: (b - a).cross(c - a).normalize(),
+            uvs,
+            normals: None,
+            material: default_material(),
+            normal,
+            dpdu,
+            dpdv,
+        }
+    }
+
+    pub fn with_uvs(self, uvs: [DVec2; 3]) -> Self {
+        let (dpdu, dpdv) = Triangle::derivatives([self.a, self.b, self.c], uvs, self.normal);
+        Triangle {
+            uvs,
+            dpdu,
+            dpdv,
+            ..self
+        }
+    }
+
+    /// normals at the vertices, interpolated over the face for smooth shading
+    pub fn with_normals(self, normals: [DVec3; 3]) -> Self {
+        Triangle {
+            normals: Some(normals.map(DVec3::normalize)),
+            ..self
         }
     }
 
+    /// solves the position derivatives from the edges and their UV deltas. Degenerate UVs get
+    /// an arbitrary tangent frame
+    fn derivatives(points: [DVec3; 3], uvs: [DVec2; 3], normal: DVec3) -> (DVec3, DVec3) {
+        let (dp1, dp2) = (points[1] - points[0], points[2] - points[0]);
+        let (duv1, duv2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+        let det = duv1.perp_dot(duv2);
+
+        if det.abs() < 1e-12 {
+            return orthonormal_basis(normal);
+        }
+
+        (
+            (duv2.y * dp1 - duv1.y * dp2) / det,
+            (duv1.x * dp2 - duv2.x * dp1) / det,
+        )
+    }
+
+    pub fn with_material(self, material: Arc<dyn Material>) -> Self {
+        Triangle { material, ..self }
+    }
+
+    /// two triangles covering the planar quad with vertices `a`, `b`, `c` and `d` in order
+    pub fn quad(a: DVec3, b: DVec3, c: DVec3, d: DVec3, material: Arc<dyn Material>) -> [Self; 2] {
+        [
+            Triangle::new(a, b, c).with_material(material.clone()),
+            Triangle::new(a, c, d).with_material(material),
+        ]
+    }
+
     pub fn from_tuples(a: (f64, f64, f64), b: (f64, f64, f64), c: (f64, f64, f64)) -> Self {
         Triangle::new(a.into(), b.into(), c.into())
     }
@@ -37,7 +105,12 @@ impl Triangle {
             a: self.c,
             b: self.b,
             c: self.a,
+            uvs: [self.uvs[2], self.uvs[1], self.uvs[0]],
+            normals: self.normals.map(|[a, b, c]| [-c, -b, -a]),
+            material: self.material.clone(),
             normal: -self.normal,
+            dpdu: self.dpdu,
+            dpdv: self.dpdv,
         }
     }
 
@@ -56,13 +129,14 @@ impl From<(DVec3, DVec3, DVec3)> for Triangle {
 }
 
 impl Intersect for Triangle {
-    fn intersect(&self, ray: Ray) -> Option<Ray> {
+    fn intersect(&self, ray: Ray) -> Option<Hit<'_>> {
         debug!("checking intersection between {:?} and {:?}", ray, self);
 
         let n = self.normal;
 
-        if n.dot(ray.dir) > 0.0 {
-            debug!("ray comes from behind triangle or ray is parallel to the triangle plane");
+        // rays coming from behind also hit the triangle, so closed meshes can be entered
+        if n.dot(ray.dir) == 0.0 {
+            debug!("ray is parallel to the triangle plane");
             return None;
         }
 
@@ -72,8 +146,8 @@ impl Intersect for Triangle {
         let t = (self.a - ray.origin).dot(n) / n.dot(ray.dir);
         debug!("t = {}", t);
 
-        if t <= 0.0 {
-            debug!("triangle is behind the ray origin, t = {}", t);
+        if !ray.contains(t) {
+            debug!("triangle is out of the ray interval, t = {}", t);
             return None;
         }
 
@@ -96,21 +170,43 @@ impl Intersect for Triangle {
         debug!("rel bc = {:?}", n.dot(bc.cross(p - self.b).normalize()));
         debug!("rel ca = {:?}", n.dot(ca.cross(p - self.c).normalize()));
 
-        let left_of_a_b = n.dot(ab.cross(p - self.a)) >= 0.0;
-        let left_of_b_c = n.dot(bc.cross(p - self.b)) >= 0.0;
-        let left_of_c_a = n.dot(ca.cross(p - self.c)) >= 0.0;
+        // twice the areas of the sub triangles opposite to each vertex
+        let area_c = n.dot(ab.cross(p - self.a));
+        let area_a = n.dot(bc.cross(p - self.b));
+        let area_b = n.dot(ca.cross(p - self.c));
+
+        let left_of_a_b = area_c >= 0.0;
+        let left_of_b_c = area_a >= 0.0;
+        let left_of_c_a = area_b >= 0.0;
 
         debug!("= {} {}", ab.cross(p - self.a).normalize(), n);
 
         if left_of_a_b && left_of_b_c && left_of_c_a {
-            let mut rng = nanorand::tls_rng();
-            let rand = DVec3::new(
-                rng.generate::<f64>() - 0.5,
-                rng.generate::<f64>() - 0.5,
-                rng.generate::<f64>() - 0.5,
-            ) * 1.2;
-
-            Some(Ray::new((p + 0.0001 * n).into(), (n + rand).normalize().into()))
+            let area = area_a + area_b + area_c;
+            let barycentric = DVec3::new(area_a, area_b, area_c) / area;
+
+            // kept on the side of the face normal, which vertex normals far from it can leave
+            let normal = self.normals.map_or(n, |[a, b, c]| {
+                let interpolated = barycentric.x * a + barycentric.y * b + barycentric.z * c;
+                face_forward(interpolated.try_normalize().unwrap_or(n), -n)
+            });
+
+            Some(Hit {
+                t,
+                point: p,
+                normal,
+                geometric_normal: n,
+                front_face: n.dot(ray.dir) < 0.0,
+                uv: barycentric.x * self.uvs[0]
+                    + barycentric.y * self.uvs[1]
+                    + barycentric.z * self.uvs[2],
+                dpdu: self.dpdu,
+                dpdv: self.dpdv,
+                barycentric,
+                material: self.material.as_ref(),
+                object: self,
+                primitive: 0,
+            })
         } else {
             None
         }
@@ -130,15 +226,46 @@ impl Intersect for Triangle {
             ),
         }
     }
+
+    fn material(&self) -> Option<&dyn Material> {
+        Some(self.material.as_ref())
+    }
+
+    fn sample(&self, origin: DVec3) -> Option<SurfaceSample> {
+        let (point, normal) = self.sample_point()?;
+        let pdf = self.sample_pdf(origin, point);
+        (pdf > 0.0).then_some(SurfaceSample { point, normal, pdf })
+    }
+
+    fn sample_pdf(&self, origin: DVec3, point: DVec3) -> f64 {
+        let to_point = point - origin;
+        let cos = self.normal.dot(to_point.normalize()).abs();
+        let area = self.area();
+        if cos > 0.0 && area > 0.0 {
+            // area density converted to solid angle
+            to_point.length_squared() / (cos * area)
+        } else {
+            0.0
+        }
+    }
+
+    fn area(&self) -> f64 {
+        0.5 * (self.b - self.a).cross(self.c - self.a).length()
+    }
+
+    fn sample_point(&self) -> Option<(DVec3, DVec3)> {
+        Some((uniform_triangle(self.a, self.b, self.c), self.normal))
+    }
 }
 
 #[cfg(test)]
 mod test {
 
-    use glam::DVec3;
+    use glam::{DVec2, DVec3};
 
     use crate::{
         geometry::{Intersect, Ray},
+        material::default_material,
         object::triangle::Triangle,
     };
 
@@ -216,6 +343,84 @@ mod test {
         assert!((tri.opposite().normal() - (-DVec3::Z)).length() < 1e-9);
     }
 
+    #[test]
+    fn quad_triangles_share_the_normal() {
+        let [first, second] = Triangle::quad(
+            DVec3::ZERO,
+            DVec3::X,
+            DVec3::X + DVec3::Y,
+            DVec3::Y,
+            default_material(),
+        );
+
+        assert!(first.normal().abs_diff_eq(DVec3::Z, 1e-9));
+        assert!(second.normal().abs_diff_eq(DVec3::Z, 1e-9));
+        assert!(first
+            .intersect(Ray::from_to((0.75, 0.25, 1.0), (0.75, 0.25, -1.0)))
+            .is_some());
+        assert!(second
+            .intersect(Ray::from_to((0.25, 0.75, 1.0), (0.25, 0.75, -1.0)))
+            .is_some());
+    }
+
+    #[test]
+    fn uvs_are_interpolated() {
+        let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (0.0, 2.0, 0.0))
+            .with_uvs([
+                DVec2::new(0.5, 0.5),
+                DVec2::new(1.0, 0.5),
+                DVec2::new(0.5, 1.0),
+            ]);
+
+        let at_a = tri
+            .intersect(Ray::from_to((0.0, 0.0, 1.0), (0.0, 0.0, -1.0)))
+            .unwrap();
+        assert!(at_a.uv.abs_diff_eq(DVec2::new(0.5, 0.5), 1e-9));
+
+        let inside = tri
+            .intersect(Ray::from_to((0.5, 1.0, 1.0), (0.5, 1.0, -1.0)))
+            .unwrap();
+        assert!(inside.uv.abs_diff_eq(DVec2::new(0.625, 0.75), 1e-9));
+    }
+
+    #[test]
+    fn vertex_normals_are_interpolated() {
+        let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (2.0, 0.0, 0.0), (0.0, 2.0, 0.0))
+            .with_normals([
+                DVec3::Z,
+                DVec3::new(1.0, 0.0, 1.0),
+                DVec3::new(0.0, 1.0, 1.0),
+            ]);
+
+        let at_a = tri
+            .intersect(Ray::from_to((0.0, 0.0, 1.0), (0.0, 0.0, -1.0)))
+            .unwrap();
+        assert!(at_a.normal.abs_diff_eq(DVec3::Z, 1e-9));
+
+        // halfway between b and c
+        let hit = tri
+            .intersect(Ray::from_to((1.0, 1.0, 1.0), (1.0, 1.0, -1.0)))
+            .unwrap();
+        assert!(hit
+            .normal
+            .abs_diff_eq(DVec3::new(1.0, 1.0, 2.0).normalize(), 1e-9));
+        assert!(hit.geometric_normal.abs_diff_eq(DVec3::Z, 1e-9));
+    }
+
+    #[test]
+    fn tangents_follow_the_uvs() {
+        // u grows along +y and v along -x
+        let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (0.0, 2.0, 0.0), (-4.0, 0.0, 0.0))
+            .with_uvs([DVec2::ZERO, DVec2::X, DVec2::Y]);
+
+        let hit = tri
+            .intersect(Ray::from_to((-0.5, 0.5, 1.0), (-0.5, 0.5, -1.0)))
+            .unwrap();
+
+        assert!(hit.dpdu.abs_diff_eq(DVec3::new(0.0, 2.0, 0.0), 1e-9));
+        assert!(hit.dpdv.abs_diff_eq(DVec3::new(-4.0, 0.0, 0.0), 1e-9));
+    }
+
     #[test]
     fn simple_intersection() {
         let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0));
@@ -234,11 +439,11 @@ mod test {
 
         let ray_center_into = Ray::from_to((0.25, 0.25, 1.0), (0.25, 0.25, -1.0));
 
-        assert!(tri.intersect(ray_center_into).unwrap().dir.z > 0.0);
+        assert!(tri.intersect(ray_center_into).unwrap().normal.z > 0.0);
     }
 
     #[test]
-    fn triangle_does_not_intersect_from_its_back() {
+    fn triangle_intersects_from_its_back() {
         let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (0.0, 1.0, 0.0), (1.0, 0.0, 0.0));
 
         // normal points to -z
@@ -246,7 +451,18 @@ mod test {
 
         let ray_center_into = Ray::from_to((0.25, 0.25, 1.0), (0.25, 0.25, -1.0));
 
-        assert!(tri.intersect(ray_center_into).is_none());
+        let hit = tri.intersect(ray_center_into).unwrap();
+        assert!(hit.point.abs_diff_eq(DVec3::new(0.25, 0.25, 0.0), 1e-9));
+        assert!(hit.normal.z < 0.0);
+    }
+
+    #[test]
+    fn triangle_does_not_intersect_parallel_ray() {
+        let tri = Triangle::from_tuples((0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0));
+
+        let ray_parallel = Ray::from_to((-1.0, 0.25, 0.0), (1.0, 0.25, 0.0));
+
+        assert!(tri.intersect(ray_parallel).is_none());
     }
 
     #[test]
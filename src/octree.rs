@@ -1,6 +1,4 @@
-use std::cmp::Ordering;
-
-use crate::geometry::{AABBox, Intersect, Ray};
+use crate::geometry::{AABBox, Hit, Intersect, Ray};
 
 #[derive(Debug)]
 pub struct Octree<'objects> {
@@ -11,7 +9,8 @@ pub struct Octree<'objects> {
 pub struct Octant<'objects> {
     bbox: AABBox,
     children: [Option<Box<Octant<'objects>>>; 8],
-    objects: Vec<&'objects dyn Intersect>,
+    /// objects with their index in the list the octree was built from
+    objects: Vec<(usize, &'objects dyn Intersect)>,
 }
 
 impl<'objects> Octree<'objects> {
@@ -21,10 +20,11 @@ impl<'objects> Octree<'objects> {
         max_objects_in_leaf: usize,
         bbox: AABBox,
     ) -> Octree<'objects> {
+        let objects = objects.iter().copied().enumerate().collect();
         Octree {
             root: Some(Octant::new(
                 bbox,
-                objects,
+                &objects,
                 max_depth,
                 1,
                 max_objects_in_leaf,
@@ -36,7 +36,7 @@ impl<'objects> Octree<'objects> {
 impl<'objects> Octant<'objects> {
     fn new(
         bbox: AABBox,
-        objects: &Vec<&'objects dyn Intersect>,
+        objects: &Vec<(usize, &'objects dyn Intersect)>,
         // 1-based
         max_depth: usize,
         cur_depth: usize,
@@ -46,7 +46,7 @@ impl<'objects> Octant<'objects> {
             return Octant {
                 bbox,
                 children: [None, None, None, None, None, None, None, None],
-                objects: objects.into_iter().copied().collect(),
+                objects: objects.to_vec(),
             };
         }
 
@@ -65,7 +65,7 @@ impl<'objects> Octant<'objects> {
 
         for obj in objects {
             for i in 0..8 {
-                if bboxes[i].intersect_other(&obj.bounds()) {
+                if bboxes[i].intersect_other(&obj.1.bounds()) {
                     objects_in_child[i].push(*obj);
                 }
             }
@@ -92,45 +92,112 @@ impl<'objects> Octant<'objects> {
 }
 
 impl<'objects> Intersect for Octree<'objects> {
-    fn intersect(&self, ray: Ray) -> Option<Ray> {
+    fn intersect(&self, ray: Ray) -> Option<Hit<'_>> {
         self.root
             .as_ref()
             .map(|octant| octant.intersect(ray))
             .unwrap()
     }
 
+    fn occluded(&self, ray: Ray) -> bool {
+        self.root
+            .as_ref()
+            .is_some_and(|octant| octant.occluded(ray))
+    }
+
     fn bounds(&self) -> AABBox {
         self.root.as_ref().map(|oct| oct.bbox).unwrap_or_default()
     }
 }
 
 impl<'objects> Intersect for Octant<'objects> {
-    fn intersect(&self, ray: Ray) -> Option<Ray> {
-        if self.bbox.intersect(ray).is_none() {
+    fn intersect(&self, mut ray: Ray) -> Option<Hit<'_>> {
+        if !self.bbox.intersect(ray) {
             return None;
         }
 
-        let child_intersects = self
-            .children
-            .iter()
-            .filter_map(|child| child.as_ref())
-            .filter_map(|child| child.intersect(ray));
-
-        let object_intersects = self.objects.iter().filter_map(|obj| obj.intersect(ray));
-
-        // get nearest
-        child_intersects.chain(object_intersects).min_by(|a, b| {
-            let dist_a = ray.origin.distance_squared(a.origin);
-            let dist_b = ray.origin.distance_squared(b.origin);
-            if dist_a < dist_b {
-                Ordering::Less
-            } else {
-                Ordering::Greater
+        // each hit shortens the ray, so only nearer objects are hit after it
+        let mut nearest = None;
+        for &(index, obj) in &self.objects {
+            if let Some(hit) = obj.intersect(ray) {
+                ray.t_max = hit.t;
+                nearest = Some(Hit {
+                    primitive: index,
+                    ..hit
+                });
+            }
+        }
+
+        // children in the order the ray enters them, up to the nearest hit
+        let mut children: [(f64, Option<&Octant>); 8] = [(f64::INFINITY, None); 8];
+        for (entered, child) in children.iter_mut().zip(&self.children) {
+            if let Some(child) = child {
+                if let Some(entry) = child.bbox.entry(ray) {
+                    *entered = (entry, Some(child));
+                }
+            }
+        }
+        children.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (entry, child) in children {
+            let Some(child) = child else {
+                break;
+            };
+            if entry > ray.t_max {
+                break;
             }
-        })
+            if let Some(hit) = child.intersect(ray) {
+                ray.t_max = hit.t;
+                nearest = Some(hit);
+            }
+        }
+
+        nearest
+    }
+
+    fn occluded(&self, ray: Ray) -> bool {
+        self.bbox.intersect(ray)
+            && (self.objects.iter().any(|(_, obj)| obj.occluded(ray))
+                || self
+                    .children
+                    .iter()
+                    .flatten()
+                    .any(|child| child.occluded(ray)))
     }
 
     fn bounds(&self) -> AABBox {
         self.bbox
     }
 }
+
+#[cfg(test)]
+mod test {
+    use glam::DVec3;
+
+    use crate::{
+        geometry::{Intersect, Ray},
+        object::sphere::Sphere,
+        octree::Octree,
+    };
+
+    #[test]
+    fn nearest_hit_has_the_index_of_its_object() {
+        let spheres: Vec<Sphere> = (0..40)
+            .map(|i| Sphere::new((0.0, 0.0, -(i as f64)), 0.3))
+            .collect();
+        let objects: Vec<&dyn Intersect> = spheres.iter().map(|s| s as &dyn Intersect).collect();
+        let bounds = objects
+            .iter()
+            .map(|obj| obj.bounds())
+            .reduce(|a, b| a.union(&b))
+            .unwrap();
+        let octree = Octree::new(&objects, 10, 2, bounds);
+
+        let hit = octree
+            .intersect(Ray::new((0.0, 0.0, -12.5), (0.0, 0.0, -1.0)))
+            .unwrap();
+        assert_eq!(hit.primitive, 13);
+        assert!((hit.t - 0.2).abs() < 1e-9);
+        assert!(hit.point.abs_diff_eq(DVec3::new(0.0, 0.0, -12.7), 1e-9));
+    }
+}
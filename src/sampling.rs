@@ -0,0 +1,325 @@
+use std::f64::consts::PI;
+
+use glam::{DVec2, DVec3};
+use nanorand::Rng;
+
+/// two unit vectors forming an orthonormal basis with the unit vector `n`
+pub fn orthonormal_basis(n: DVec3) -> (DVec3, DVec3) {
+    // Duff et al., "Building an Orthonormal Basis, Revisited"
+    let sign = 1.0f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    (
+        DVec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        DVec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// direction in the hemisphere around `normal` with probability density cos(theta) / pi
+pub fn cosine_hemisphere(normal: DVec3) -> DVec3 {
+    let mut rng = nanorand::tls_rng();
+    let r = rng.generate::<f64>().sqrt();
+    let phi = 2.0 * PI * rng.generate::<f64>();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    let z = (1.0 - r * r).max(0.0).sqrt();
+
+    (r * phi.cos() * tangent + r * phi.sin() * bitangent + z * normal).normalize()
+}
+
+/// uniformly distributed direction, with probability density 1 / (4 pi)
+pub fn uniform_sphere() -> DVec3 {
+    let mut rng = nanorand::tls_rng();
+    let z = 1.0 - 2.0 * rng.generate::<f64>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.generate::<f64>();
+
+    DVec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// direction uniformly distributed in the cone around `axis` with half angle acos(cos_max),
+/// with probability density 1 / (2 pi (1 - cos_max))
+pub fn uniform_cone(axis: DVec3, cos_max: f64) -> DVec3 {
+    let mut rng = nanorand::tls_rng();
+    let cos_theta = 1.0 - rng.generate::<f64>() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.generate::<f64>();
+
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    (sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * axis)
+        .normalize()
+}
+
+/// uniformly distributed point of the unit disk
+pub fn uniform_disk() -> DVec2 {
+    let mut rng = nanorand::tls_rng();
+    let r = rng.generate::<f64>().sqrt();
+    let phi = 2.0 * PI * rng.generate::<f64>();
+
+    r * DVec2::new(phi.cos(), phi.sin())
+}
+
+/// uniformly distributed point of the triangle `a`, `b`, `c`
+pub fn uniform_triangle(a: DVec3, b: DVec3, c: DVec3) -> DVec3 {
+    let mut rng = nanorand::tls_rng();
+    let s = rng.generate::<f64>().sqrt();
+    let t = rng.generate::<f64>();
+
+    (1.0 - s) * a + s * (1.0 - t) * b + s * t * c
+}
+
+/// Piecewise constant distribution over [0, 1), proportional to the values of its segments
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    values: Vec<f64>,
+    /// cumulative distribution at the start of each segment, and 1 at the end
+    cdf: Vec<f64>,
+    /// mean of the values
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// distribution of `values`, which must not be negative. All zero values give a uniform
+    /// distribution
+    pub fn new(values: Vec<f64>) -> Self {
+        let n = values.len() as f64;
+        let mut cdf = vec![0.0; values.len() + 1];
+        for (i, value) in values.iter().enumerate() {
+            cdf[i + 1] = cdf[i] + value / n;
+        }
+
+        let integral = cdf[values.len()];
+        for (i, cdf) in cdf.iter_mut().enumerate().skip(1) {
+            *cdf = if integral > 0.0 {
+                *cdf / integral
+            } else {
+                i as f64 / n
+            };
+        }
+
+        Distribution1D {
+            values,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// random point with its density, and the index of the segment it is in
+    pub fn sample(&self) -> (f64, f64, usize) {
+        let u = nanorand::tls_rng().generate::<f64>();
+
+        // last segment starting at or before u
+        let index = self
+            .cdf
+            .partition_point(|cdf| *cdf <= u)
+            .clamp(1, self.values.len())
+            - 1;
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+
+        let x = (index as f64 + offset) / self.values.len() as f64;
+        (x, self.pdf(x), index)
+    }
+
+    /// density of `sample` returning `x`
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.values.len();
+        if self.integral <= 0.0 {
+            return 1.0;
+        }
+
+        let index = ((x * n as f64) as usize).min(n - 1);
+        self.values[index] / self.integral
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)^2, proportional to the values of a grid stored
+/// row by row
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    /// distribution of the position along each row
+    rows: Vec<Distribution1D>,
+    /// distribution of the row
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(values: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(values.len(), width * height);
+
+        let rows: Vec<Distribution1D> = values
+            .chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        Distribution2D { rows, marginal }
+    }
+
+    /// random point, as (column, row) coordinates, with its density
+    pub fn sample(&self) -> (DVec2, f64) {
+        let (v, pdf_v, row) = self.marginal.sample();
+        let (u, pdf_u, _) = self.rows[row].sample();
+
+        (DVec2::new(u, v), pdf_u * pdf_v)
+    }
+
+    /// density of `sample` returning `point`
+    pub fn pdf(&self, point: DVec2) -> f64 {
+        let row = ((point.y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(point.y) * self.rows[row].pdf(point.x)
+    }
+}
+
+/// multiple importance sampling weight of a sample taken with density `pdf` when another
+/// strategy could have taken it with density `other_pdf`
+pub fn balance_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    if pdf <= 0.0 {
+        return 0.0;
+    }
+
+    pdf / (pdf + other_pdf)
+}
+
+/// like the balance heuristic, but favoring further the strategy with the higher density,
+/// which reduces variance when one of them is much better
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    balance_heuristic(pdf * pdf, other_pdf * other_pdf)
+}
+
+#[cfg(test)]
+mod test {
+    use glam::DVec3;
+
+    use crate::sampling::{
+        balance_heuristic, cosine_hemisphere, orthonormal_basis, power_heuristic, uniform_cone,
+        uniform_sphere, Distribution1D, Distribution2D,
+    };
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for n in [
+            DVec3::X,
+            -DVec3::Z,
+            DVec3::Z,
+            DVec3::new(1.0, 2.0, -3.0).normalize(),
+        ] {
+            let (t, b) = orthonormal_basis(n);
+            assert!((t.length() - 1.0).abs() < 1e-9);
+            assert!((b.length() - 1.0).abs() < 1e-9);
+            assert!(t.dot(n).abs() < 1e-9);
+            assert!(b.dot(n).abs() < 1e-9);
+            assert!(t.dot(b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cosine_samples_average_to_two_thirds() {
+        // E[cos(theta)] for the density cos(theta) / pi is 2/3
+        let n = 100_000;
+        let normal = DVec3::new(0.0, 1.0, 1.0).normalize();
+
+        let mean = (0..n)
+            .map(|_| cosine_hemisphere(normal).dot(normal))
+            .inspect(|cos| assert!(*cos >= 0.0))
+            .sum::<f64>()
+            / n as f64;
+
+        assert!((mean - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn sphere_samples_are_centered() {
+        let n = 100_000;
+
+        let mean = (0..n)
+            .map(|_| uniform_sphere())
+            .inspect(|dir| assert!((dir.length() - 1.0).abs() < 1e-9))
+            .fold(DVec3::ZERO, |sum, dir| sum + dir)
+            / n as f64;
+
+        assert!(mean.length() < 0.01);
+    }
+
+    #[test]
+    fn cone_samples_stay_in_the_cone() {
+        let axis = DVec3::new(1.0, -1.0, 0.5).normalize();
+        let cos_max = 0.9;
+
+        for _ in 0..10_000 {
+            let dir = uniform_cone(axis, cos_max);
+            assert!(dir.dot(axis) >= cos_max - 1e-9);
+        }
+    }
+
+    #[test]
+    fn heuristic_weights_add_up_to_one() {
+        for (a, b) in [(1.0, 1.0), (0.1, 10.0), (3.0, 0.0), (1e-8, 1e6)] {
+            assert!((balance_heuristic(a, b) + balance_heuristic(b, a) - 1.0).abs() < 1e-9);
+            assert!((power_heuristic(a, b) + power_heuristic(b, a) - 1.0).abs() < 1e-9);
+        }
+
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert!(power_heuristic(2.0, 1.0) > balance_heuristic(2.0, 1.0));
+    }
+
+    #[test]
+    fn distribution_follows_the_values() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, 0.0]);
+        let n = 100_000;
+
+        let mut counts = [0; 4];
+        for _ in 0..n {
+            let (x, pdf, index) = distribution.sample();
+            assert_eq!(index, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[index] += 1;
+        }
+
+        assert_eq!((counts[1], counts[3]), (0, 0));
+        assert!((counts[2] as f64 / n as f64 - 0.75).abs() < 0.01);
+        // densities of the segments, which are a quarter wide
+        assert_eq!(distribution.pdf(0.1), 1.0);
+        assert_eq!(distribution.pdf(0.6), 3.0);
+    }
+
+    #[test]
+    fn zero_distribution_is_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 3]);
+
+        for _ in 0..100 {
+            let (x, pdf, _) = distribution.sample();
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn distribution_2d_integrates_to_one() {
+        let values = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let distribution = Distribution2D::new(&values, 3, 2);
+
+        // the mean of 1 / pdf over the samples estimates the area where the density is not 0
+        let n = 10_000;
+        let area = (0..n)
+            .map(|_| {
+                let (point, pdf) = distribution.sample();
+                assert!((distribution.pdf(point) - pdf).abs() < 1e-9);
+                assert!(point.x >= 1.0 / 3.0 || point.y >= 0.5);
+                1.0 / pdf
+            })
+            .sum::<f64>()
+            / n as f64;
+
+        assert!((area - 5.0 / 6.0).abs() < 0.02, "{area}");
+    }
+}
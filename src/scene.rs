@@ -1,20 +1,35 @@
+use std::sync::Arc;
+
 use glam::DVec3;
 
 use crate::{
     camera::Camera,
+    color::{blackbody, Color},
     geometry::Intersect,
-    object::{import_from_wavefront_obj_file, sphere::Sphere, triangle::Triangle},
+    light::{sky::SunSky, DirectionalLight, EnvironmentLight, Light, PointLight, SpotLight},
+    material::{Dielectric, Diffuse, Emissive, Material, MediumBoundary, Microfacet, Mirror},
+    medium::Medium,
+    object::{
+        import_from_wavefront_obj_file_with_shading, sphere::Sphere, triangle::Triangle, Shading,
+    },
+    texture::procedural::{Checker, Marble, Space},
 };
 
+pub type CalcFrameFn = Box<dyn Fn(&mut Scene, usize)>;
+
 pub struct MovieScene {
     pub scene: Scene,
     pub n_frames: usize,
-    pub calc_frame_fn: Option<Box<dyn Fn(&mut Scene, usize)>>,
+    pub calc_frame_fn: Option<CalcFrameFn>,
 }
 
 pub struct Scene {
+    /// all objects, including the area lights, which have emissive materials
     pub objects: Vec<Box<dyn Intersect>>,
-    pub lights: Vec<Sphere>,
+    /// lights that are not objects, like point and spot lights
+    pub lights: Vec<Box<dyn Light>>,
+    /// medium filling the space around the objects, like fog
+    pub medium: Option<Medium>,
     pub camera: Camera,
 }
 
@@ -26,24 +41,60 @@ impl MovieScene {
     }
 }
 
+/// dark checkerboard with `scale` squares per unit, making scale and motion easy to read
+fn checkered_floor(scale: f64) -> Arc<dyn Material> {
+    Arc::new(Diffuse::textured(Arc::new(Checker::new(
+        Color::splat(0.1),
+        Color::splat(0.4),
+        scale,
+        Space::World,
+    ))))
+}
+
 #[allow(unused)]
 pub fn spheres() -> MovieScene {
     let cam_origin = DVec3::new(0.0, 0.0, 8.0);
     MovieScene {
         scene: Scene {
             objects: vec![
-                Box::new(Sphere::new((0.0, 0.0, 0.0), 2.0)),
-                Box::new(Sphere::new((5.0, 0.0, -3.0), 2.0)),
-                Box::new(Sphere::new((-2.5, 0.0, 2.0), 2.0)),
-                Box::new(Sphere::new((0.5, -1.5, 2.0), 1.0)),
-                Box::new(Sphere::new((2.1, 2.1, 2.0), 0.6)),
-                Box::new(Triangle::from_tuples(
-                    (-100.0, -10.0, 100.0),
-                    (100.0, -10.0, 100.0),
-                    (0.0, -10.0, -200.0),
-                )),
+                Box::new(
+                    Sphere::new((0.0, 0.0, 0.0), 2.0)
+                        .with_material(Arc::new(Mirror::new(Color::new(0.9, 0.9, 0.9)))),
+                ),
+                Box::new(Sphere::new((5.0, 0.0, -3.0), 2.0).with_material(Arc::new(
+                    Microfacet::metal(Color::new(1.0, 0.78, 0.34), 0.3),
+                ))),
+                Box::new(Sphere::new((-2.5, 0.0, 2.0), 2.0).with_material(Arc::new(
+                    Diffuse::textured(Arc::new(Marble::new(
+                        Color::new(0.85, 0.85, 0.9),
+                        Color::new(0.1, 0.1, 0.4),
+                        2.0,
+                        4.0,
+                        Space::World,
+                    ))),
+                ))),
+                Box::new(
+                    Sphere::new((0.5, -1.5, 2.0), 1.0).with_material(Arc::new(Dielectric::glass())),
+                ),
+                Box::new(Sphere::new((2.1, 2.1, 2.0), 0.6).with_material(Arc::new(
+                    Microfacet::plastic(Color::new(0.9, 0.2, 0.2), 0.4),
+                ))),
+                Box::new(
+                    Triangle::from_tuples(
+                        (-100.0, -10.0, 100.0),
+                        (100.0, -10.0, 100.0),
+                        (0.0, -10.0, -200.0),
+                    )
+                    .with_material(checkered_floor(0.25)),
+                ),
+                // light
+                Box::new(
+                    Sphere::new((20.0, 30.0, 20.0), 10.0)
+                        .with_material(Arc::new(Emissive::new(Color::new(1.0, 0.95, 0.85)))),
+                ),
             ],
-            lights: vec![Sphere::new((20.0, 30.0, 20.0), 10.0)],
+            lights: Vec::new(),
+            medium: None,
             camera: Camera::new(
                 cam_origin,
                 (DVec3::new(0.0, 0.0, 0.0) - cam_origin).normalize(),
@@ -58,6 +109,302 @@ pub fn spheres() -> MovieScene {
     }
 }
 
+/// spheres under a ceiling light panel
+#[allow(unused)]
+pub fn ceiling_panel() -> MovieScene {
+    let white = Arc::new(Diffuse::new(Color::new(0.8, 0.8, 0.8)));
+    let panel = Arc::new(Emissive::new(Color::new(4.0, 4.0, 3.8)));
+
+    let mut objects: Vec<Box<dyn Intersect>> = vec![
+        Box::new(Sphere::new((-1.5, -1.0, 0.0), 1.0).with_material(Arc::new(Dielectric::glass()))),
+        Box::new(
+            Sphere::new((1.5, -1.0, -0.5), 1.0)
+                .with_material(Arc::new(Diffuse::new(Color::new(0.8, 0.3, 0.2)))),
+        ),
+    ];
+
+    let floor = Triangle::quad(
+        DVec3::new(-5.0, -2.0, 5.0),
+        DVec3::new(5.0, -2.0, 5.0),
+        DVec3::new(5.0, -2.0, -5.0),
+        DVec3::new(-5.0, -2.0, -5.0),
+        checkered_floor(1.0),
+    );
+    let back_wall = Triangle::quad(
+        DVec3::new(-5.0, -2.0, -5.0),
+        DVec3::new(5.0, -2.0, -5.0),
+        DVec3::new(5.0, 4.0, -5.0),
+        DVec3::new(-5.0, 4.0, -5.0),
+        white,
+    );
+    let light_panel = Triangle::quad(
+        DVec3::new(-1.5, 3.0, -1.5),
+        DVec3::new(1.5, 3.0, -1.5),
+        DVec3::new(1.5, 3.0, 1.5),
+        DVec3::new(-1.5, 3.0, 1.5),
+        panel,
+    );
+
+    for triangle in floor.into_iter().chain(back_wall).chain(light_panel) {
+        objects.push(Box::new(triangle));
+    }
+
+    let cam_origin = DVec3::new(0.0, 0.5, 6.0);
+    let fov = 70.0f64.to_radians();
+    let camera = Camera::new(
+        cam_origin,
+        (DVec3::new(0.0, -0.5, 0.0) - cam_origin).normalize(),
+        DVec3::new(0.0, -1.0, 0.0).normalize(),
+        fov,
+        fov,
+        2.0,
+    );
+
+    MovieScene {
+        scene: Scene {
+            objects,
+            lights: Vec::new(),
+            medium: None,
+            camera,
+        },
+        n_frames: 1,
+        calc_frame_fn: None,
+    }
+}
+
+/// spheres lit by a stage rig: a spot as key light, a point light as fill and a directional
+/// rim light from behind
+#[allow(unused)]
+pub fn light_rig() -> MovieScene {
+    let objects: Vec<Box<dyn Intersect>> =
+        vec![
+            Box::new(
+                Sphere::new((-1.2, -1.0, 0.0), 1.0)
+                    .with_material(Arc::new(Diffuse::new(Color::new(0.8, 0.3, 0.2)))),
+            ),
+            Box::new(Sphere::new((1.2, -1.0, -0.5), 1.0).with_material(Arc::new(
+                Microfacet::plastic(Color::new(0.2, 0.4, 0.8), 0.3),
+            ))),
+            Box::new(
+                Triangle::from_tuples(
+                    (-100.0, -2.0, 100.0),
+                    (100.0, -2.0, 100.0),
+                    (0.0, -2.0, -200.0),
+                )
+                .with_material(checkered_floor(1.0)),
+            ),
+        ];
+
+    // warm key, neutral fill and cold rim
+    let lights: Vec<Box<dyn Light>> = vec![
+        Box::new(SpotLight::with_power(
+            DVec3::new(-3.0, 5.0, 4.0),
+            DVec3::new(3.0, -6.0, -4.0),
+            blackbody(3200.0),
+            25.0,
+            15.0f64.to_radians(),
+            30.0f64.to_radians(),
+        )),
+        Box::new(PointLight::with_power(
+            DVec3::new(5.0, 1.0, 5.0),
+            blackbody(5500.0),
+            40.0,
+        )),
+        Box::new(DirectionalLight::new(
+            DVec3::new(0.0, -0.5, 1.0),
+            0.3 * blackbody(10000.0),
+        )),
+    ];
+
+    let cam_origin = DVec3::new(0.0, 0.5, 6.0);
+    let fov = 70.0f64.to_radians();
+    let camera = Camera::new(
+        cam_origin,
+        (DVec3::new(0.0, -1.0, 0.0) - cam_origin).normalize(),
+        DVec3::new(0.0, -1.0, 0.0).normalize(),
+        fov,
+        fov,
+        2.0,
+    );
+
+    MovieScene {
+        scene: Scene {
+            objects,
+            lights,
+            medium: None,
+            camera,
+        },
+        n_frames: 1,
+        calc_frame_fn: None,
+    }
+}
+
+/// spot light shining through a foggy room onto a sphere, next to a cloud of smoke, making the
+/// light beam visible
+#[allow(unused)]
+pub fn foggy_stage() -> MovieScene {
+    let objects: Vec<Box<dyn Intersect>> = vec![
+        Box::new(
+            Sphere::new((0.8, -1.0, -0.5), 1.0)
+                .with_material(Arc::new(Diffuse::new(Color::new(0.8, 0.3, 0.2)))),
+        ),
+        Box::new(Sphere::new((-1.8, -0.8, -1.0), 1.2).with_material(Arc::new(
+            MediumBoundary::new(Medium::fog(Color::splat(0.9), 1.5, 0.3)),
+        ))),
+        Box::new(
+            Triangle::from_tuples(
+                (-100.0, -2.0, 100.0),
+                (100.0, -2.0, 100.0),
+                (0.0, -2.0, -200.0),
+            )
+            .with_material(checkered_floor(1.0)),
+        ),
+    ];
+
+    let lights: Vec<Box<dyn Light>> = vec![
+        Box::new(SpotLight::with_power(
+            DVec3::new(3.0, 5.0, -2.0),
+            DVec3::new(-2.0, -6.0, 1.5),
+            blackbody(4500.0),
+            60.0,
+            10.0f64.to_radians(),
+            20.0f64.to_radians(),
+        )),
+        Box::new(PointLight::with_power(
+            DVec3::new(-4.0, 3.0, 5.0),
+            blackbody(8000.0),
+            5.0,
+        )),
+    ];
+
+    let cam_origin = DVec3::new(0.0, 0.5, 6.0);
+    let fov = 70.0f64.to_radians();
+    let camera = Camera::new(
+        cam_origin,
+        (DVec3::new(0.0, -0.5, 0.0) - cam_origin).normalize(),
+        DVec3::new(0.0, -1.0, 0.0).normalize(),
+        fov,
+        fov,
+        2.0,
+    );
+
+    MovieScene {
+        scene: Scene {
+            objects,
+            lights,
+            medium: Some(Medium::fog(Color::ONE, 0.05, 0.5)),
+            camera,
+        },
+        n_frames: 1,
+        calc_frame_fn: None,
+    }
+}
+
+/// camera going around material samples lit by the HDR environment map at `hdr_path`
+#[allow(unused)]
+pub fn hdri_turntable(hdr_path: &str) -> MovieScene {
+    let objects: Vec<Box<dyn Intersect>> =
+        vec![
+            Box::new(Sphere::new((-2.2, 0.0, 0.0), 1.0).with_material(Arc::new(
+                Microfacet::metal(Color::new(1.0, 0.78, 0.34), 0.2),
+            ))),
+            Box::new(
+                Sphere::new((0.0, 0.0, 0.0), 1.0).with_material(Arc::new(Dielectric::glass())),
+            ),
+            Box::new(Sphere::new((2.2, 0.0, 0.0), 1.0).with_material(Arc::new(
+                Microfacet::plastic(Color::new(0.1, 0.3, 0.8), 0.4),
+            ))),
+            Box::new(
+                Triangle::from_tuples(
+                    (-100.0, -1.0, 100.0),
+                    (100.0, -1.0, 100.0),
+                    (0.0, -1.0, -200.0),
+                )
+                .with_material(Arc::new(Diffuse::new(Color::splat(0.5)))),
+            ),
+        ];
+    let lights: Vec<Box<dyn Light>> = vec![Box::new(EnvironmentLight::load_hdr(hdr_path, 1.0))];
+
+    let fov = 60.0f64.to_radians();
+    let camera = Camera::new(
+        DVec3::new(0.0, 1.5, 7.0),
+        DVec3::new(0.0, -1.5, -7.0).normalize(),
+        DVec3::new(0.0, -1.0, 0.0),
+        fov,
+        fov,
+        2.0,
+    );
+
+    let n_frames = 64;
+    let calc_frame_fn = Box::new(move |scene: &mut Scene, frame: usize| {
+        let angle = frame as f64 / n_frames as f64 * 2.0 * std::f64::consts::PI;
+        scene.camera.origin = DVec3::new(7.0 * angle.sin(), 1.5, 7.0 * angle.cos());
+        scene.camera.dir = (-scene.camera.origin).normalize();
+        scene.camera.recalc();
+    });
+
+    MovieScene {
+        scene: Scene {
+            objects,
+            lights,
+            medium: None,
+            camera,
+        },
+        n_frames,
+        calc_frame_fn: Some(calc_frame_fn),
+    }
+}
+
+/// spheres on a checkered floor under the afternoon sun
+#[allow(unused)]
+pub fn outdoor() -> MovieScene {
+    let objects: Vec<Box<dyn Intersect>> =
+        vec![
+            Box::new(
+                Sphere::new((-1.2, -1.0, 0.0), 1.0)
+                    .with_material(Arc::new(Diffuse::new(Color::splat(0.8)))),
+            ),
+            Box::new(Sphere::new((1.2, -1.0, -0.5), 1.0).with_material(Arc::new(
+                Microfacet::plastic(Color::new(0.8, 0.2, 0.1), 0.3),
+            ))),
+            Box::new(
+                Triangle::from_tuples(
+                    (-100.0, -2.0, 100.0),
+                    (100.0, -2.0, 100.0),
+                    (0.0, -2.0, -200.0),
+                )
+                .with_material(checkered_floor(1.0)),
+            ),
+        ];
+
+    // radiance in kcd/m^2 scaled so that white surfaces in full sun come out close to white
+    let lights: Vec<Box<dyn Light>> = vec![Box::new(
+        SunSky::new(35.0f64.to_radians(), 150.0f64.to_radians(), 3.0).with_scale(0.03),
+    )];
+
+    let cam_origin = DVec3::new(0.0, 0.5, 6.0);
+    let fov = 70.0f64.to_radians();
+    let camera = Camera::new(
+        cam_origin,
+        (DVec3::new(0.0, -1.0, 0.0) - cam_origin).normalize(),
+        DVec3::new(0.0, -1.0, 0.0).normalize(),
+        fov,
+        fov,
+        2.0,
+    );
+
+    MovieScene {
+        scene: Scene {
+            objects,
+            lights,
+            medium: None,
+            camera,
+        },
+        n_frames: 1,
+        calc_frame_fn: None,
+    }
+}
+
 #[allow(unused)]
 pub fn icosahedron() -> MovieScene {
     let mut ico = spinning_icosahedron();
@@ -84,7 +431,7 @@ pub fn spinning_icosahedron() -> MovieScene {
         DVec3::new(0.000000, 1.000000, 0.000000) * 2.0,
     ];
 
-    let objects: Vec<Box<dyn Intersect>> = vec![
+    let mut objects: Vec<Box<dyn Intersect>> = vec![
         Box::new(Triangle::new(p[1], p[2], p[3])),
         Box::new(Triangle::new(p[2], p[1], p[6])),
         Box::new(Triangle::new(p[1], p[3], p[4])),
@@ -105,14 +452,20 @@ pub fn spinning_icosahedron() -> MovieScene {
         Box::new(Triangle::new(p[9], p[8], p[12])),
         Box::new(Triangle::new(p[10], p[9], p[12])),
         Box::new(Triangle::new(p[11], p[10], p[12])),
-        Box::new(Triangle::from_tuples(
-            (-100.0, -5.0, 100.0),
-            (100.0, -5.0, 100.0),
-            (0.0, -5.0, -200.0),
-        )),
+        Box::new(
+            Triangle::from_tuples(
+                (-100.0, -5.0, 100.0),
+                (100.0, -5.0, 100.0),
+                (0.0, -5.0, -200.0),
+            )
+            .with_material(checkered_floor(0.25)),
+        ),
     ];
 
-    let lights = vec![Sphere::new((40.0, 30.0, 0.0), 15.0)];
+    // light
+    objects.push(Box::new(
+        Sphere::new((40.0, 30.0, 0.0), 15.0).with_material(Arc::new(Emissive::new(Color::ONE))),
+    ));
 
     let cam_origin = DVec3::new(0.0, 0.0, 8.0);
     let fov = 90.0f64.to_radians();
@@ -138,7 +491,8 @@ pub fn spinning_icosahedron() -> MovieScene {
     MovieScene {
         scene: Scene {
             objects,
-            lights,
+            lights: Vec::new(),
+            medium: None,
             camera,
         },
         n_frames,
@@ -148,15 +502,26 @@ pub fn spinning_icosahedron() -> MovieScene {
 
 #[allow(unused)]
 pub fn scene_from_obj_file() -> MovieScene {
-    let lights = vec![Sphere::new((40.0, 30.0, 0.0), 15.0)];
-    let mut objects = import_from_wavefront_obj_file("./torus.obj");
+    let mut objects = import_from_wavefront_obj_file_with_shading(
+        "./torus.obj",
+        // exported with flat normals
+        Shading::Smooth { crease_angle: 60.0 },
+    );
 
     // floor
-    objects.push(Box::new(Triangle::from_tuples(
-        (-100.0, -75.0, 100.0),
-        (100.0, -75.0, 100.0),
-        (0.0, -75.0, -200.0),
-    )));
+    objects.push(Box::new(
+        Triangle::from_tuples(
+            (-100.0, -75.0, 100.0),
+            (100.0, -75.0, 100.0),
+            (0.0, -75.0, -200.0),
+        )
+        .with_material(checkered_floor(0.25)),
+    ));
+
+    // light
+    objects.push(Box::new(
+        Sphere::new((40.0, 30.0, 0.0), 15.0).with_material(Arc::new(Emissive::new(Color::ONE))),
+    ));
 
     let cam_origin = DVec3::new(0.0, 2.0, 2.0);
     let fov = 90.0f64.to_radians();
@@ -171,9 +536,10 @@ pub fn scene_from_obj_file() -> MovieScene {
 
     MovieScene {
         scene: Scene {
+            medium: None,
             camera,
-            lights,
             objects,
+            lights: Vec::new(),
         },
         n_frames: 1,
         calc_frame_fn: None,
@@ -182,17 +548,28 @@ pub fn scene_from_obj_file() -> MovieScene {
 
 #[allow(unused)]
 pub fn icosphere() -> MovieScene {
-    let lights = vec![Sphere::new((40.0, 30.0, 0.0), 15.0)];
-    let mut objects = import_from_wavefront_obj_file("./icosphere.obj");
+    let mut objects = import_from_wavefront_obj_file_with_shading(
+        "./icosphere.obj",
+        // exported with flat normals
+        Shading::Smooth { crease_angle: 60.0 },
+    );
 
     println!("loaded {} triangles", objects.len());
 
     // floor
-    objects.push(Box::new(Triangle::from_tuples(
-        (-100.0, -75.0, 100.0),
-        (100.0, -75.0, 100.0),
-        (0.0, -75.0, -200.0),
-    )));
+    objects.push(Box::new(
+        Triangle::from_tuples(
+            (-100.0, -75.0, 100.0),
+            (100.0, -75.0, 100.0),
+            (0.0, -75.0, -200.0),
+        )
+        .with_material(checkered_floor(0.25)),
+    ));
+
+    // light
+    objects.push(Box::new(
+        Sphere::new((40.0, 30.0, 0.0), 15.0).with_material(Arc::new(Emissive::new(Color::ONE))),
+    ));
 
     let cam_origin = DVec3::new(0.0, 0.0, 2.1);
     let fov = 90.0f64.to_radians();
@@ -207,9 +584,10 @@ pub fn icosphere() -> MovieScene {
 
     MovieScene {
         scene: Scene {
+            medium: None,
             camera,
-            lights,
             objects,
+            lights: Vec::new(),
         },
         n_frames: 1,
         calc_frame_fn: None,
@@ -0,0 +1,132 @@
+use std::{fmt::Debug, path::Path};
+
+use glam::{DVec2, DVec3};
+
+use crate::{
+    color::Color,
+    image::{load_png, IMAGE_GAMMA},
+};
+
+pub mod perlin;
+pub mod procedural;
+
+/// Color varying over a surface
+pub trait Texture: Sync + Send + Debug {
+    /// color at the surface point `point` with texture coordinates `uv`
+    fn value(&self, uv: DVec2, point: DVec3) -> Color;
+
+    /// single value, from the first channel, for textures driving scalar parameters
+    fn scalar(&self, uv: DVec2, point: DVec3) -> f64 {
+        self.value(uv, point).x
+    }
+}
+
+/// Constant color
+impl Texture for Color {
+    fn value(&self, _uv: DVec2, _point: DVec3) -> Color {
+        *self
+    }
+}
+
+/// Image mapped to the UV space, repeating outside of [0, 1]
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// linear colors, row by row from the top
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// image with colors, stored gamma encoded. None if the file can't be read as a PNG
+    pub fn load_png(path: impl AsRef<Path>) -> Option<Self> {
+        let (pixels, width, height) = load_png(path, IMAGE_GAMMA)?;
+        Some(ImageTexture::new(width, height, pixels))
+    }
+
+    /// image with data stored as is, like normal and bump maps. None if the file can't be read
+    /// as a PNG
+    pub fn load_png_data(path: impl AsRef<Path>) -> Option<Self> {
+        let (pixels, width, height) = load_png(path, 1.0)?;
+        Some(ImageTexture::new(width, height, pixels))
+    }
+
+    fn pixel(&self, x: isize, y: isize) -> Color {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, uv: DVec2, _point: DVec3) -> Color {
+        // v grows upwards, rows grow downwards. Pixel centers are at half integers
+        let x = uv.x * self.width as f64 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f64 - 0.5;
+
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        // bilinear filtering
+        let top = self.pixel(x0, y0).lerp(self.pixel(x0 + 1, y0), tx);
+        let bottom = self.pixel(x0, y0 + 1).lerp(self.pixel(x0 + 1, y0 + 1), tx);
+        top.lerp(bottom, ty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{DVec2, DVec3};
+
+    use crate::{
+        color::Color,
+        texture::{ImageTexture, Texture},
+    };
+
+    fn checker_2x2() -> ImageTexture {
+        ImageTexture::new(2, 2, vec![Color::X, Color::Y, Color::Z, Color::ONE])
+    }
+
+    #[test]
+    fn pixel_centers_are_exact() {
+        let texture = checker_2x2();
+
+        // top left, top right, bottom left, bottom right
+        assert_eq!(texture.value(DVec2::new(0.25, 0.75), DVec3::ZERO), Color::X);
+        assert_eq!(texture.value(DVec2::new(0.75, 0.75), DVec3::ZERO), Color::Y);
+        assert_eq!(texture.value(DVec2::new(0.25, 0.25), DVec3::ZERO), Color::Z);
+        assert_eq!(
+            texture.value(DVec2::new(0.75, 0.25), DVec3::ZERO),
+            Color::ONE
+        );
+    }
+
+    #[test]
+    fn texture_repeats() {
+        let texture = checker_2x2();
+
+        assert_eq!(
+            texture.value(DVec2::new(1.25, -0.25), DVec3::ZERO),
+            texture.value(DVec2::new(0.25, 0.75), DVec3::ZERO)
+        );
+    }
+
+    #[test]
+    fn texture_is_interpolated_between_pixels() {
+        let texture = checker_2x2();
+
+        let middle_top = texture.value(DVec2::new(0.5, 0.75), DVec3::ZERO);
+
+        assert!(middle_top.abs_diff_eq(Color::new(0.5, 0.5, 0.0), 1e-9));
+    }
+}
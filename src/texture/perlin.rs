@@ -0,0 +1,165 @@
+use glam::DVec3;
+use nanorand::{Rng, WyRand};
+
+/// Improved Perlin gradient noise
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    /// shuffled 0..256, repeated twice to avoid wrapping the indices
+    permutation: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: Vec<usize> = (0..256).collect();
+        WyRand::new_seed(seed).shuffle(&mut table);
+
+        Perlin {
+            permutation: table.iter().chain(table.iter()).copied().collect(),
+        }
+    }
+
+    /// noise in about [-1, 1], zero at the integer lattice points
+    pub fn noise(&self, point: DVec3) -> f64 {
+        let cell = point.floor();
+        let local = point - cell;
+
+        let x = cell.x.rem_euclid(256.0) as usize;
+        let y = cell.y.rem_euclid(256.0) as usize;
+        let z = cell.z.rem_euclid(256.0) as usize;
+
+        let p = &self.permutation;
+        let hash = |dx: usize, dy: usize, dz: usize| p[p[p[x + dx] + y + dy] + z + dz];
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            gradient(
+                hash(dx, dy, dz),
+                local - DVec3::new(dx as f64, dy as f64, dz as f64),
+            )
+        };
+
+        let u = fade(local.x);
+        let v = fade(local.y);
+        let w = fade(local.z);
+
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    /// fractional Brownian motion: octaves of noise at doubling frequencies and halving
+    /// amplitudes, normalized to about [-1, 1]
+    pub fn fbm(&self, point: DVec3, octaves: usize) -> f64 {
+        self.octaves(point, octaves, |noise| noise)
+    }
+
+    /// like fbm but summing the absolute value of each octave, in about [0, 1]
+    pub fn turbulence(&self, point: DVec3, octaves: usize) -> f64 {
+        self.octaves(point, octaves, f64::abs)
+    }
+
+    fn octaves(&self, point: DVec3, octaves: usize, shape: impl Fn(f64) -> f64) -> f64 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut point = point;
+
+        for _ in 0..octaves.max(1) {
+            sum += amplitude * shape(self.noise(point));
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            point *= 2.0;
+        }
+
+        sum / total_amplitude
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// dot product of `offset` with one of the 12 gradients of the cube edge midpoints
+fn gradient(hash: usize, offset: DVec3) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { offset.x } else { offset.y };
+    let v = if h < 4 {
+        offset.y
+    } else if h == 12 || h == 14 {
+        offset.x
+    } else {
+        offset.z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod test {
+    use glam::DVec3;
+
+    use crate::texture::perlin::Perlin;
+
+    #[test]
+    fn noise_is_zero_at_lattice_points() {
+        let perlin = Perlin::new(7);
+
+        for point in [
+            DVec3::ZERO,
+            DVec3::new(3.0, -2.0, 17.0),
+            DVec3::splat(300.0),
+        ] {
+            assert_eq!(perlin.noise(point), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_bounded_continuous_and_not_constant() {
+        let perlin = Perlin::new(7);
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+
+        for i in 0..10_000 {
+            let point = DVec3::new(i as f64 * 0.173, i as f64 * 0.071, -(i as f64) * 0.037);
+            let noise = perlin.noise(point);
+            let nearby = perlin.noise(point + DVec3::splat(1e-4));
+
+            assert!((-1.1..=1.1).contains(&noise));
+            assert!((noise - nearby).abs() < 1e-3);
+            min = min.min(noise);
+            max = max.max(noise);
+        }
+
+        assert!(max - min > 0.5);
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        let point = DVec3::new(0.3, 1.7, -4.2);
+
+        assert_eq!(Perlin::new(1).noise(point), Perlin::new(1).noise(point));
+        assert_ne!(Perlin::new(1).noise(point), Perlin::new(2).noise(point));
+    }
+
+    #[test]
+    fn turbulence_is_positive() {
+        let perlin = Perlin::new(3);
+
+        for i in 0..1000 {
+            let turbulence = perlin.turbulence(DVec3::splat(i as f64 * 0.113), 5);
+            assert!((0.0..=1.0).contains(&turbulence));
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::sync::Arc;
+
+use glam::{DVec2, DVec3};
+
+use crate::{
+    color::Color,
+    texture::{perlin::Perlin, Texture},
+};
+
+/// Coordinates procedural textures are evaluated in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    /// texture coordinates, as (u, v, 0)
+    Uv,
+    /// hit point in world coordinates
+    World,
+}
+
+/// Alternating cubes (or squares in UV space) of two textures
+#[derive(Clone, Debug)]
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    /// number of cells per unit
+    pub scale: f64,
+    pub space: Space,
+}
+
+/// Alternating bands of two textures across `direction`
+#[derive(Clone, Debug)]
+pub struct Stripes {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub direction: DVec3,
+    /// number of stripes per unit
+    pub scale: f64,
+    pub space: Space,
+}
+
+/// Linear blend between two colors from `start` to `end`, constant past them
+#[derive(Clone, Copy, Debug)]
+pub struct Gradient {
+    pub from: Color,
+    pub to: Color,
+    pub start: DVec3,
+    pub end: DVec3,
+    pub space: Space,
+}
+
+/// Fractal Perlin noise blending between two colors
+#[derive(Clone, Debug)]
+pub struct Noise {
+    pub low: Color,
+    pub high: Color,
+    /// frequency of the first octave
+    pub scale: f64,
+    pub octaves: usize,
+    pub space: Space,
+    perlin: Perlin,
+}
+
+/// Veins of `vein` over `base` along x, distorted by turbulence
+#[derive(Clone, Debug)]
+pub struct Marble {
+    pub base: Color,
+    pub vein: Color,
+    /// frequency of the veins
+    pub scale: f64,
+    /// how much the turbulence bends the veins
+    pub turbulence: f64,
+    pub space: Space,
+    perlin: Perlin,
+}
+
+/// Concentric rings around the y axis, distorted by turbulence
+#[derive(Clone, Debug)]
+pub struct Wood {
+    pub light: Color,
+    pub dark: Color,
+    /// number of rings per unit
+    pub rings: f64,
+    /// how much the turbulence bends the rings
+    pub turbulence: f64,
+    pub space: Space,
+    perlin: Perlin,
+}
+
+/// number of octaves of the turbulence in marble and wood
+const TURBULENCE_OCTAVES: usize = 6;
+
+impl Space {
+    fn coords(&self, uv: DVec2, point: DVec3) -> DVec3 {
+        match self {
+            Space::Uv => uv.extend(0.0),
+            Space::World => point,
+        }
+    }
+}
+
+impl Checker {
+    pub fn new(even: Color, odd: Color, scale: f64, space: Space) -> Self {
+        Checker {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            scale,
+            space,
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let cell = (self.space.coords(uv, point) * self.scale).floor();
+
+        if (cell.x + cell.y + cell.z).rem_euclid(2.0) == 0.0 {
+            self.even.value(uv, point)
+        } else {
+            self.odd.value(uv, point)
+        }
+    }
+}
+
+impl Stripes {
+    pub fn new(even: Color, odd: Color, direction: DVec3, scale: f64, space: Space) -> Self {
+        Stripes {
+            even: Arc::new(even),
+            odd: Arc::new(odd),
+            direction: direction.normalize(),
+            scale,
+            space,
+        }
+    }
+}
+
+impl Texture for Stripes {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let band = (self.space.coords(uv, point).dot(self.direction) * self.scale).floor();
+
+        if band.rem_euclid(2.0) == 0.0 {
+            self.even.value(uv, point)
+        } else {
+            self.odd.value(uv, point)
+        }
+    }
+}
+
+impl Gradient {
+    pub fn new(from: Color, to: Color, start: DVec3, end: DVec3, space: Space) -> Self {
+        Gradient {
+            from,
+            to,
+            start,
+            end,
+            space,
+        }
+    }
+}
+
+impl Texture for Gradient {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let axis = self.end - self.start;
+        let t = (self.space.coords(uv, point) - self.start).dot(axis) / axis.length_squared();
+
+        self.from.lerp(self.to, t.clamp(0.0, 1.0))
+    }
+}
+
+impl Noise {
+    pub fn new(low: Color, high: Color, scale: f64, octaves: usize, space: Space) -> Self {
+        Noise {
+            low,
+            high,
+            scale,
+            octaves,
+            space,
+            perlin: Perlin::new(0),
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Noise {
+            perlin: Perlin::new(seed),
+            ..self
+        }
+    }
+}
+
+impl Texture for Noise {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let p = self.space.coords(uv, point) * self.scale;
+        let t = 0.5 + 0.5 * self.perlin.fbm(p, self.octaves);
+
+        self.low.lerp(self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+impl Marble {
+    pub fn new(base: Color, vein: Color, scale: f64, turbulence: f64, space: Space) -> Self {
+        Marble {
+            base,
+            vein,
+            scale,
+            turbulence,
+            space,
+            perlin: Perlin::new(0),
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Marble {
+            perlin: Perlin::new(seed),
+            ..self
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let p = self.space.coords(uv, point) * self.scale;
+        let phase = p.x + self.turbulence * self.perlin.turbulence(p, TURBULENCE_OCTAVES);
+
+        // sharpen the veins
+        let t = (1.0 - (0.5 + 0.5 * phase.sin())).powi(3);
+
+        self.base.lerp(self.vein, t)
+    }
+}
+
+impl Wood {
+    pub fn new(light: Color, dark: Color, rings: f64, turbulence: f64, space: Space) -> Self {
+        Wood {
+            light,
+            dark,
+            rings,
+            turbulence,
+            space,
+            perlin: Perlin::new(0),
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Wood {
+            perlin: Perlin::new(seed),
+            ..self
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, uv: DVec2, point: DVec3) -> Color {
+        let p = self.space.coords(uv, point);
+        let distance = (p.x * p.x + p.z * p.z).sqrt() * self.rings;
+        let ring = (distance
+            + self.turbulence * self.perlin.turbulence(p * self.rings, TURBULENCE_OCTAVES))
+        .fract();
+
+        // soft transition from early to late wood
+        self.light.lerp(self.dark, ring * ring)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::{DVec2, DVec3};
+
+    use crate::{
+        color::Color,
+        texture::{
+            procedural::{Checker, Gradient, Marble, Noise, Space, Stripes, Wood},
+            Texture,
+        },
+    };
+
+    #[test]
+    fn checker_alternates_in_world_space() {
+        let checker = Checker::new(Color::ZERO, Color::ONE, 1.0, Space::World);
+
+        let value = |x, y, z| checker.value(DVec2::ZERO, DVec3::new(x, y, z));
+
+        assert_eq!(value(0.5, 0.5, 0.5), Color::ZERO);
+        assert_eq!(value(1.5, 0.5, 0.5), Color::ONE);
+        assert_eq!(value(1.5, 1.5, 0.5), Color::ZERO);
+        assert_eq!(value(-0.5, 0.5, 0.5), Color::ONE);
+    }
+
+    #[test]
+    fn checker_alternates_in_uv_space() {
+        let checker = Checker::new(Color::ZERO, Color::ONE, 4.0, Space::Uv);
+
+        let value = |u, v| checker.value(DVec2::new(u, v), DVec3::splat(100.0));
+
+        assert_eq!(value(0.1, 0.1), Color::ZERO);
+        assert_eq!(value(0.3, 0.1), Color::ONE);
+        assert_eq!(value(0.3, 0.3), Color::ZERO);
+    }
+
+    #[test]
+    fn stripes_only_vary_across_direction() {
+        let stripes = Stripes::new(Color::ZERO, Color::ONE, DVec3::X, 2.0, Space::World);
+
+        let value = |x, y| stripes.value(DVec2::ZERO, DVec3::new(x, y, 0.0));
+
+        assert_eq!(value(0.1, 0.0), value(0.1, 7.3));
+        assert_ne!(value(0.1, 0.0), value(0.6, 0.0));
+    }
+
+    #[test]
+    fn gradient_blends_between_endpoints() {
+        let gradient = Gradient::new(Color::ZERO, Color::ONE, DVec3::ZERO, DVec3::Y, Space::Uv);
+
+        let value = |v| gradient.value(DVec2::new(0.3, v), DVec3::ZERO);
+
+        assert_eq!(value(0.0), Color::ZERO);
+        assert!(value(0.25).abs_diff_eq(Color::splat(0.25), 1e-9));
+        assert_eq!(value(1.0), Color::ONE);
+        assert_eq!(value(2.0), Color::ONE);
+    }
+
+    #[test]
+    fn noisy_textures_stay_between_their_colors() {
+        let low = Color::new(0.1, 0.2, 0.3);
+        let high = Color::new(0.9, 0.8, 0.7);
+        let textures: [Box<dyn Texture>; 3] = [
+            Box::new(Noise::new(low, high, 3.0, 4, Space::World)),
+            Box::new(Marble::new(low, high, 4.0, 5.0, Space::World).with_seed(9)),
+            Box::new(Wood::new(low, high, 6.0, 0.3, Space::World)),
+        ];
+
+        for texture in textures {
+            for i in 0..1000 {
+                let point = DVec3::new(i as f64 * 0.31, i as f64 * -0.17, i as f64 * 0.05);
+                let color = texture.value(DVec2::ZERO, point);
+
+                assert!(color.cmpge(low - 1e-9).all() && color.cmple(high + 1e-9).all());
+            }
+        }
+    }
+}
@@ -5,22 +5,254 @@ use std::sync::{
 
 use glam::DVec3;
 use log::debug;
+use nanorand::Rng;
 
 use crate::{
-    geometry::{AABBox, Intersect, Ray},
-    object::sphere::Sphere,
+    color::Color,
+    geometry::{Hit, Intersect, Ray, RAY_EPSILON},
+    integrator::{Integrator, Splats},
+    light::{AreaLight, Light, LightSample},
+    medium::Medium,
     octree::Octree,
+    sampling::power_heuristic,
     scene::Scene,
 };
 
+/// Objects rays can hit and the lights that can be sampled directly
+pub struct World<'a> {
+    pub objects: Octree<'a>,
+    pub lights: Vec<Box<dyn Light + 'a>>,
+    /// medium around the objects
+    pub medium: Option<Medium>,
+}
+
+impl<'a> World<'a> {
+    /// octree of `objects`, with the emissive ones as lights
+    pub fn new(objects: &'a [Box<dyn Intersect>]) -> Self {
+        let objects: Vec<&dyn Intersect> = objects.iter().map(|obj| obj.as_ref()).collect();
+
+        debug!("Constructing octree...");
+        let octree = Octree::new(
+            &objects,
+            10,
+            16,
+            objects
+                .iter()
+                .map(|obj| obj.bounds())
+                .reduce(|a, b| a.union(&b))
+                .unwrap_or_default(),
+        );
+        debug!("Octree construction done");
+
+        let lights = objects
+            .iter()
+            .filter_map(|obj| AreaLight::new(*obj))
+            .map(|light| Box::new(light) as Box<dyn Light>)
+            .collect();
+
+        World {
+            objects: octree,
+            lights,
+            medium: None,
+        }
+    }
+
+    /// adds lights that are not objects, like point lights
+    pub fn with_lights(mut self, lights: &'a [Box<dyn Light>]) -> Self {
+        self.lights.extend(
+            lights
+                .iter()
+                .map(|light| Box::new(light.as_ref()) as Box<dyn Light>),
+        );
+        self
+    }
+
+    /// fills the space around the objects with `medium`
+    pub fn with_medium(mut self, medium: Option<Medium>) -> Self {
+        self.medium = medium;
+        self
+    }
+
+    /// random light, sampled from `point`, and the density of the sample including the choice
+    /// of the light
+    fn sample_light(&self, point: DVec3) -> Option<(&dyn Light, LightSample, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let light = self.lights[nanorand::tls_rng().generate_range(0..self.lights.len())].as_ref();
+        let sample = light.sample(point)?;
+        // one light out of all of them is chosen with probability 1 / len
+        let light_pdf = sample.pdf / self.lights.len() as f64;
+        if light_pdf <= 0.0 {
+            return None;
+        }
+
+        Some((light, sample, light_pdf))
+    }
+
+    /// light arriving at the hit from one light chosen at random, reflected towards the ray
+    /// origin. Weighted against the BSDF sampling the same direction. `medium` is the one the
+    /// ray went through
+    fn direct_light(&self, ray: Ray, hit: &Hit, medium: Option<Medium>) -> Color {
+        let Some((light, sample, light_pdf)) = self.sample_light(hit.point) else {
+            return Color::ZERO;
+        };
+
+        let wo = -ray.dir;
+        let reflected = hit.material.eval(hit, wo, sample.dir);
+        if reflected == Color::ZERO {
+            return Color::ZERO;
+        }
+        let transmittance = self.transmittance(
+            hit.spawn_shadow_ray(sample.dir, sample.distance),
+            self.medium_after(hit, ray.dir, sample.dir, medium),
+        );
+        if transmittance == Color::ZERO {
+            return Color::ZERO;
+        }
+
+        // delta lights can't be reached by BSDF samples
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, hit.material.pdf(hit, wo, sample.dir))
+        };
+        weight * reflected * transmittance * sample.radiance / light_pdf
+    }
+
+    /// light arriving at `point` inside `medium` from one light chosen at random, scattered
+    /// towards `wo`. Weighted against the phase function sampling the same direction
+    fn direct_light_in_medium(&self, point: DVec3, wo: DVec3, medium: Medium) -> Color {
+        let Some((light, sample, light_pdf)) = self.sample_light(point) else {
+            return Color::ZERO;
+        };
+
+        let phase = medium.phase(wo, sample.dir);
+        // points in media are not on a surface, the ray only stops before the light
+        let shadow_ray = Ray::new(point.into(), sample.dir.into())
+            .with_interval(0.0, sample.distance * (1.0 - RAY_EPSILON));
+        let transmittance = self.transmittance(shadow_ray, Some(medium));
+        if transmittance == Color::ZERO {
+            return Color::ZERO;
+        }
+
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, phase)
+        };
+        weight * phase * transmittance * sample.radiance / light_pdf
+    }
+
+    /// radiance of the lights seen by a ray missing every object, like environment maps
+    fn escaped(&self, ray: Ray, sampled: Sampled) -> Color {
+        self.lights
+            .iter()
+            .map(|light| {
+                let radiance = light.le(ray.dir);
+                if radiance == Color::ZERO {
+                    return radiance;
+                }
+
+                let weight = match sampled {
+                    Sampled::Specular => 1.0,
+                    Sampled::Bsdf { origin, pdf } => {
+                        power_heuristic(pdf, light.pdf(origin, ray.dir) / self.lights.len() as f64)
+                    }
+                };
+                weight * radiance
+            })
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+    }
+
+    /// weight of the emission found by a ray, against sampling the light it hit directly
+    fn emission_weight(&self, hit: &Hit, sampled: Sampled) -> f64 {
+        match sampled {
+            Sampled::Specular => 1.0,
+            Sampled::Bsdf { origin, pdf } => {
+                let light_pdf = if self.lights.is_empty() {
+                    0.0
+                } else {
+                    hit.object.sample_pdf(origin, hit.point) / self.lights.len() as f64
+                };
+                power_heuristic(pdf, light_pdf)
+            }
+        }
+    }
+
+    /// if something is hit within the interval of the ray, like between a point and a light.
+    /// Medium boundaries let the ray through, integrators other than the path tracer see
+    /// through media
+    pub fn occluded(&self, mut ray: Ray) -> bool {
+        // the any-hit query settles unoccluded rays, only hits are checked for medium boundaries
+        while self.objects.occluded(ray) {
+            let Some(hit) = self.objects.intersect(ray) else {
+                return false;
+            };
+            if hit.material.medium().is_none() {
+                return true;
+            }
+            ray = hit.spawn_ray(ray.dir).with_interval(0.0, ray.t_max - hit.t);
+        }
+        false
+    }
+
+    /// fraction of the light going along the ray that is left at the end of its interval,
+    /// after the media on the way, starting with `medium`. Zero if an object other than a
+    /// medium boundary is in the way
+    pub fn transmittance(&self, mut ray: Ray, mut medium: Option<Medium>) -> Color {
+        let mut transmittance = Color::ONE;
+        loop {
+            let hit = self.objects.intersect(ray);
+            let segment = hit.map_or(ray.t_max, |hit| hit.t) - ray.t_min;
+            if let Some(medium) = medium {
+                transmittance *= medium.transmittance(segment);
+            }
+
+            let Some(hit) = hit else {
+                return transmittance;
+            };
+            if hit.material.medium().is_none() {
+                return Color::ZERO;
+            }
+            medium = self.medium_after(&hit, ray.dir, ray.dir, medium);
+            ray = hit.spawn_ray(ray.dir).with_interval(0.0, ray.t_max - hit.t);
+        }
+    }
+
+    /// medium a ray leaving the hit towards `dir` goes through, for a ray that arrived along
+    /// `incoming` through `medium`. Rays going into an object find the medium of medium
+    /// boundaries and nothing inside other objects, rays leaving it find the medium around the
+    /// objects. Reflected rays stay in the same medium
+    fn medium_after(
+        &self,
+        hit: &Hit,
+        incoming: DVec3,
+        dir: DVec3,
+        medium: Option<Medium>,
+    ) -> Option<Medium> {
+        let normal = hit.geometric_normal;
+        let crossed = incoming.dot(normal) * dir.dot(normal) > 0.0;
+        if !crossed {
+            medium
+        } else if dir.dot(normal) < 0.0 {
+            hit.material.medium().copied()
+        } else {
+            self.medium
+        }
+    }
+}
+
+/// renders the scene with the colors computed by `integrator`, prepared for the scene first
 pub fn render(
     scene: &Scene,
     x_res: usize,
     y_res: usize,
     num_threads: usize,
     samples_per_pixel: usize,
-    max_reflections: usize,
-    image: &mut Vec<f64>,
+    integrator: &mut dyn Integrator,
+    image: &mut [Color],
 ) {
     let y_block_size = y_res / num_threads;
 
@@ -28,117 +260,281 @@ pub fn render(
 
     let pixels_rendered = Arc::new(AtomicUsize::new(0));
 
-    debug!("Constructing octree...");
-    let octree = &Octree::new(
-        &scene
-            .objects
-            .iter()
-            .map(|obj_box| obj_box.as_ref())
-            .collect(),
-        10,
-        16,
-        AABBox {
-            min: DVec3::new(-10.0, -10.0, -10.0),
-            max: DVec3::new(10.0, 10.0, 10.0),
-        },
-    );
-    debug!("Octree construction done");
-
-    let _ = crossbeam::scope(|scope| {
-        for (thread_num, chunk) in image.chunks_mut(y_block_size * x_res).enumerate() {
-            let pixels_counter = pixels_rendered.clone();
-            scope.spawn(move |_| {
-                for y in 0..y_block_size {
-                    let mut curr = 0;
-                    for x in 0..x_res {
-                        let abs_x = x;
-                        let abs_y = thread_num * y_block_size + y;
-                        for _ in 0..samples_per_pixel {
-                            let ray = scene.camera.ray((abs_x, abs_y), (x_res, y_res));
-
-                            chunk[y * x_res + x] += (1.0 / samples_per_pixel as f64)
-                                * trace_ray(ray, octree, &scene.lights, max_reflections + 1);
+    let world = &World::new(&scene.objects)
+        .with_lights(&scene.lights)
+        .with_medium(scene.medium);
+
+    integrator.preprocess(world);
+    let integrator = &*integrator;
+
+    let splats: Vec<Splats> = crossbeam::scope(|scope| {
+        let threads: Vec<_> = image
+            .chunks_mut(y_block_size * x_res)
+            .enumerate()
+            .map(|(thread_num, chunk)| {
+                let pixels_counter = pixels_rendered.clone();
+                scope.spawn(move |_| {
+                    let mut splats = Splats::new(x_res, y_res);
+                    for y in 0..y_block_size {
+                        let mut curr = 0;
+                        for x in 0..x_res {
+                            let abs_x = x;
+                            let abs_y = thread_num * y_block_size + y;
+                            for _ in 0..samples_per_pixel {
+                                let ray = scene.camera.ray((abs_x, abs_y), (x_res, y_res));
+
+                                chunk[y * x_res + x] += (1.0 / samples_per_pixel as f64)
+                                    * integrator.sample(ray, world, &scene.camera, &mut splats);
+                            }
+                            curr = pixels_counter.fetch_add(1, Ordering::Acquire) + 1;
                         }
-                        curr = pixels_counter.fetch_add(1, Ordering::Acquire) + 1;
+                        println!(
+                            "{}/{} pixels rendered. {:.1}%",
+                            curr,
+                            x_res * y_res,
+                            curr as f64 / (x_res as f64 * y_res as f64) * 100.0
+                        );
                     }
-                    println!(
-                        "{}/{} pixels rendered. {:.1}%",
-                        curr,
-                        x_res * y_res,
-                        curr as f64 / (x_res as f64 * y_res as f64) * 100.0
-                    );
-                }
-            });
+                    splats
+                })
+            })
+            .collect();
+
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    })
+    .unwrap();
+
+    // light splatted by every thread, over the same number of samples as the pixels
+    for thread_splats in splats {
+        for (pixel, splat) in image.iter_mut().zip(thread_splats.pixels) {
+            *pixel += splat / samples_per_pixel as f64;
         }
-    });
+    }
+}
+
+/// bounces always traced before Russian roulette can end a path
+const ROULETTE_MIN_BOUNCES: usize = 3;
+/// highest probability of a path surviving Russian roulette, so that even paths keeping all
+/// their energy end eventually
+const ROULETTE_MAX_SURVIVAL: f64 = 0.95;
+
+/// How the ray being traced was chosen
+#[derive(Clone, Copy, Debug)]
+enum Sampled {
+    /// camera rays and specular bounces, which light sampling can't reproduce
+    Specular,
+    /// BSDF sample from `origin` with solid angle density `pdf`
+    Bsdf { origin: DVec3, pdf: f64 },
+}
+
+/// State of the path leading to the ray being traced
+#[derive(Clone, Copy, Debug)]
+struct Path {
+    bounces: usize,
+    /// fraction of the radiance found by the ray reaching the camera
+    throughput: Color,
+    sampled: Sampled,
+    /// medium the ray goes through
+    medium: Option<Medium>,
 }
 
+/// radiance arriving along the ray. Paths end by Russian roulette, `remaining_steps` only
+/// caps their length
 #[inline]
-pub fn trace_ray(ray: Ray, objects: &Octree, lights: &[Sphere], remaining_steps: usize) -> f64 {
+pub fn trace_ray(ray: Ray, world: &World, remaining_steps: usize) -> Color {
+    let path = Path {
+        bounces: 0,
+        throughput: Color::ONE,
+        sampled: Sampled::Specular,
+        medium: world.medium,
+    };
+    trace(ray, world, remaining_steps, path)
+}
+
+/// radiance arriving along the ray. Lights are reached both by sampling them directly and by
+/// BSDF samples hitting them, the two combined with multiple importance sampling
+fn trace(ray: Ray, world: &World, remaining_steps: usize, path: Path) -> Color {
     debug!("tracing ray {:?}", ray);
+    let hit = world.objects.intersect(ray);
 
-    // find intersections
-    let mut vec = vec![];
-
-    if let Some(reflection_normal) = objects.intersect(ray) {
-        vec.push((
-            ray.origin.distance_squared(reflection_normal.origin),
-            reflection_normal,
-            false,
-        ));
-    }
-
-    for light in lights {
-        debug!("testing intersection with light {:?}", light);
-        if let Some(normal) = light.intersect(ray) {
-            debug!(
-                "ray intersects with light {:?} with distance {:.3}",
-                light,
-                (normal.origin - ray.origin).length()
-            );
-            vec.push(((normal.origin - ray.origin).length_squared(), normal, true));
-        } else {
-            debug!("no intersection with light {:?}", light);
-        }
-    }
-
-    let nearest = vec.iter().min_by(|(dist_a, _, _), (dist_b, _, _)| {
-        dist_a
-            .partial_cmp(dist_b)
-            .unwrap_or(std::cmp::Ordering::Less)
-    });
-
-    // calculate light intensity
-    if let Some((_, normal, true)) = nearest {
-        debug!("ray reaches a light source at {}", normal.origin);
-        1.0
-    } else if let Some((_, normal, false)) = nearest {
-        debug!("ray reaches an object at {}", normal.origin);
-        if remaining_steps > 1 {
-            // calculate reflected ray
-            let reflected = ray.reflect(*normal);
-            debug!("reflected ray is {:?}", reflected);
-            debug!("{} remaining steps", remaining_steps - 1);
-            0.98 * trace_ray(reflected, objects, lights, remaining_steps - 1)
-        } else {
-            debug!("no more recursion available");
-            0.0
+    let Some(medium) = path.medium else {
+        return trace_surface(ray, hit, world, remaining_steps, path);
+    };
+
+    // the ray may scatter in the medium before reaching the hit
+    let max_distance = hit.map_or(f64::INFINITY, |hit| hit.t);
+    let sample = medium.sample_distance(max_distance);
+    let path = Path {
+        throughput: path.throughput * sample.weight,
+        ..path
+    };
+    let radiance = match sample.scattered_at {
+        Some(distance) => {
+            debug!("ray scattered by the medium after {}", distance);
+            scatter_in_medium(ray, distance, medium, world, remaining_steps, path)
         }
+        None => trace_surface(ray, hit, world, remaining_steps, path),
+    };
+    sample.weight * radiance
+}
+
+/// radiance leaving the hit of the ray towards its origin, or arriving from the lights if
+/// there is no hit
+fn trace_surface(
+    ray: Ray,
+    hit: Option<Hit>,
+    world: &World,
+    remaining_steps: usize,
+    path: Path,
+) -> Color {
+    let sampled = path.sampled;
+
+    let Some(hit) = hit else {
+        return world.escaped(ray, sampled);
+    };
+
+    // medium boundaries are not part of the path, the ray just changes medium
+    if hit.material.medium().is_some() {
+        debug!("ray crosses a medium boundary at {}", hit.point);
+        let path = Path {
+            medium: world.medium_after(&hit, ray.dir, ray.dir, path.medium),
+            ..path
+        };
+        return trace(hit.spawn_ray(ray.dir), world, remaining_steps, path);
+    }
+
+    debug!("ray reaches an object at {}", hit.point);
+    let mut emitted = hit.material.emitted();
+    if emitted != Color::ZERO {
+        emitted *= world.emission_weight(&hit, sampled);
+    }
+
+    if remaining_steps > 1 {
+        // let the material scatter the ray
+        let Some(scatter) = hit.material.scatter(ray, &hit) else {
+            debug!("ray absorbed by {:?}", hit.material);
+            return emitted;
+        };
+
+        let (direct, sampled) = if scatter.specular {
+            (Color::ZERO, Sampled::Specular)
+        } else {
+            let pdf = hit.material.pdf(&hit, -ray.dir, scatter.ray.dir);
+            (
+                world.direct_light(ray, &hit, path.medium),
+                Sampled::Bsdf {
+                    origin: hit.point,
+                    pdf,
+                },
+            )
+        };
+
+        let path = Path {
+            sampled,
+            medium: world.medium_after(&hit, ray.dir, scatter.ray.dir, path.medium),
+            ..path
+        };
+        emitted
+            + direct
+            + follow(
+                scatter.ray,
+                scatter.attenuation,
+                world,
+                remaining_steps,
+                path,
+            )
     } else {
-        0.0
+        debug!("no more recursion available");
+        emitted
+    }
+}
+
+/// radiance leaving the point at `distance` along the ray inside `medium`, towards the ray
+/// origin
+fn scatter_in_medium(
+    ray: Ray,
+    distance: f64,
+    medium: Medium,
+    world: &World,
+    remaining_steps: usize,
+    path: Path,
+) -> Color {
+    if remaining_steps <= 1 {
+        debug!("no more recursion available");
+        return Color::ZERO;
+    }
+
+    let point = ray.origin + distance * ray.dir;
+    let wo = -ray.dir;
+    let direct = world.direct_light_in_medium(point, wo, medium);
+
+    // directions are sampled with the phase function itself, so they all keep the same weight
+    let wi = medium.sample_phase(wo);
+    let path = Path {
+        sampled: Sampled::Bsdf {
+            origin: point,
+            pdf: medium.phase(wo, wi),
+        },
+        ..path
+    };
+    direct
+        + follow(
+            Ray::new(point.into(), wi.into()),
+            Color::ONE,
+            world,
+            remaining_steps,
+            path,
+        )
+}
+
+/// radiance arriving along the scattered ray, reduced by `attenuation` on the way to the
+/// previous one. `path` leads to the scattered ray, without its last bounce
+fn follow(
+    ray: Ray,
+    mut attenuation: Color,
+    world: &World,
+    remaining_steps: usize,
+    path: Path,
+) -> Color {
+    // end dim paths at random, making up for it in the ones that go on
+    let throughput = path.throughput * attenuation;
+    if path.bounces >= ROULETTE_MIN_BOUNCES {
+        let survival = throughput.max_element().min(ROULETTE_MAX_SURVIVAL);
+        if nanorand::tls_rng().generate::<f64>() >= survival {
+            debug!("path ended by Russian roulette");
+            return Color::ZERO;
+        }
+        attenuation /= survival;
     }
+
+    debug!("scattered ray is {:?}", ray);
+    debug!("{} remaining steps", remaining_steps - 1);
+    let path = Path {
+        bounces: path.bounces + 1,
+        throughput: path.throughput * attenuation,
+        ..path
+    };
+    attenuation * trace(ray, world, remaining_steps - 1, path)
 }
 
 #[cfg(test)]
 mod test {
+    use std::{f64::consts::PI, sync::Arc};
+
     use glam::DVec3;
     use log::debug;
 
     use crate::{
-        geometry::{AABBox, Intersect, Ray},
+        color::Color,
+        geometry::{Hit, Intersect, Ray},
+        light::{EnvironmentLight, Light, PointLight},
+        material::{Diffuse, Emissive, Material, MediumBoundary, Mirror, Scatter},
+        medium::Medium,
         object::{sphere::Sphere, triangle::Triangle},
-        octree::Octree,
-        tracer::trace_ray,
+        tracer::{trace_ray, World},
     };
 
     #[test]
@@ -146,22 +542,283 @@ mod test {
         env_logger::init();
 
         let ray = Ray::from_to((0.0, 0.0, 8.0), (4.0, -4.0, 0.0));
-        let scene: Vec<Box<dyn Intersect>> = vec![Box::new(Triangle::from_tuples(
-            (-2.5, -4.5, 0.0),
-            (6.5, 4.5, 0.0),
-            (6.5, -4.5, 0.0),
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(Triangle::from_tuples(
+                (-2.5, -4.5, 0.0),
+                (6.5, 4.5, 0.0),
+                (6.5, -4.5, 0.0),
+            )),
+            Box::new(
+                Sphere::new((4.0, 0.0, 2.0), 1.0)
+                    .with_material(Arc::new(Emissive::new(Color::ONE))),
+            ),
+        ];
+        let world = World::new(&scene);
+
+        debug!("{:?}", trace_ray(ray, &world, 10));
+    }
+
+    #[test]
+    fn emissive_triangle_is_a_light() {
+        let color = Color::new(4.0, 2.0, 1.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![Box::new(
+            Triangle::from_tuples((-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0))
+                .with_material(Arc::new(Emissive::new(color))),
+        )];
+        let world = World::new(&scene);
+
+        let ray = Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+
+        assert_eq!(trace_ray(ray, &world, 10), color);
+    }
+
+    #[test]
+    fn reflected_light_is_scaled_by_albedo() {
+        let albedo = Color::new(0.5, 0.25, 1.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(
+                Triangle::from_tuples((-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (0.0, 1.0, 0.0))
+                    .with_material(Arc::new(Mirror::new(albedo))),
+            ),
+            Box::new(
+                Triangle::from_tuples((-1.0, -1.0, 9.0), (1.0, -1.0, 9.0), (0.0, 1.0, 9.0))
+                    .with_material(Arc::new(Emissive::new(Color::ONE))),
+            ),
+        ];
+        let world = World::new(&scene);
+
+        // goes down to the mirror and back up to the light
+        let ray = Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+
+        assert!(trace_ray(ray, &world, 10).abs_diff_eq(albedo, 1e-9));
+    }
+
+    /// diffuse surface that also emits light
+    #[derive(Debug)]
+    struct GlowingDiffuse {
+        diffuse: Diffuse,
+        emitted: Color,
+    }
+
+    impl Material for GlowingDiffuse {
+        fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
+            self.diffuse.scatter(ray, hit)
+        }
+
+        fn emitted(&self) -> Color {
+            self.emitted
+        }
+
+        fn eval(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> Color {
+            self.diffuse.eval(hit, wo, wi)
+        }
+
+        fn pdf(&self, hit: &Hit, wo: DVec3, wi: DVec3) -> f64 {
+            self.diffuse.pdf(hit, wo, wi)
+        }
+    }
+
+    /// mean radiance inside a closed sphere emitting (1 - albedo), which converges to
+    /// (1 - albedo) * (1 + albedo + albedo^2 + ...) = 1 everywhere
+    fn white_furnace_radiance(albedo: f64, steps: usize, n: usize) -> Color {
+        let scene: Vec<Box<dyn Intersect>> = vec![Box::new(
+            Sphere::new((0.0, 0.0, 0.0), 5.0).with_material(Arc::new(GlowingDiffuse {
+                diffuse: Diffuse::new(Color::splat(albedo)),
+                emitted: Color::splat(1.0 - albedo),
+            })),
+        )];
+        let world = World::new(&scene);
+
+        (0..n)
+            .map(|i| {
+                let angle = i as f64;
+                let ray = Ray::new((1.0, -2.0, 0.5), (angle.cos(), angle.sin(), 0.3));
+                trace_ray(ray, &world, steps)
+            })
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            / n as f64
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Russian roulette makes single paths noisy, hence the many samples
+        let mean = white_furnace_radiance(0.5, 64, 20_000);
+        assert!(mean.abs_diff_eq(Color::ONE, 1e-2), "{mean}");
+    }
+
+    #[test]
+    fn roulette_ends_long_paths_without_bias() {
+        // with no practical cap, paths only end by Russian roulette, and none of the light
+        // bouncing many times is lost
+        let mean = white_furnace_radiance(0.9, usize::MAX, 20_000);
+        assert!(mean.abs_diff_eq(Color::ONE, 3e-2), "{mean}");
+    }
+
+    #[test]
+    fn small_light_is_sampled_directly() {
+        // a diffuse floor with albedo a under a sphere of radius r at height h emitting l
+        // reflects a * l * r^2 / h^2, the irradiance being pi * l * sin^2 of the cone angle
+        let (albedo, radius, height, radiance) = (0.5, 0.1, 2.0, 10.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(
+                Triangle::from_tuples((-5.0, 0.0, 5.0), (5.0, 0.0, 5.0), (0.0, 0.0, -5.0))
+                    .with_material(Arc::new(Diffuse::new(Color::splat(albedo)))),
+            ),
+            Box::new(
+                Sphere::new((0.0, height, 0.0), radius)
+                    .with_material(Arc::new(Emissive::new(Color::splat(radiance)))),
+            ),
+        ];
+        let world = World::new(&scene);
+
+        let n = 1000;
+        let ray = Ray::from_to((1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+        let mean = (0..n)
+            .map(|_| trace_ray(ray, &world, 2))
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            / n as f64;
+
+        let expected = albedo * radiance * radius * radius / (height * height);
+        assert!(
+            mean.abs_diff_eq(Color::splat(expected), 0.01 * expected),
+            "{mean}"
+        );
+    }
+
+    #[test]
+    fn large_light_is_combined_with_bsdf_samples() {
+        // under a light covering the whole sky, the irradiance is pi * l and a diffuse floor
+        // reflects albedo * l
+        let (albedo, radiance) = (0.5, 2.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(
+                Triangle::from_tuples((-5.0, 0.0, 5.0), (5.0, 0.0, 5.0), (0.0, 0.0, -5.0))
+                    .with_material(Arc::new(Diffuse::new(Color::splat(albedo)))),
+            ),
+            Box::new(
+                Sphere::new((0.0, 0.0, 0.0), 10.0)
+                    .with_material(Arc::new(Emissive::new(Color::splat(radiance)))),
+            ),
+        ];
+        let world = World::new(&scene);
+
+        let n = 10_000;
+        let ray = Ray::from_to((1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+        let mean = (0..n)
+            .map(|_| trace_ray(ray, &world, 2))
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            / n as f64;
+
+        let expected = albedo * radiance;
+        assert!(
+            mean.abs_diff_eq(Color::splat(expected), 0.01 * expected),
+            "{mean}"
+        );
+    }
+
+    #[test]
+    fn point_light_casts_shadows() {
+        // a diffuse floor reflects albedo / pi * intensity / distance^2 from a light above it
+        let (albedo, intensity, height) = (0.5, 8.0, 2.0);
+        let floor = Triangle::from_tuples((-5.0, 0.0, 5.0), (5.0, 0.0, 5.0), (0.0, 0.0, -5.0))
+            .with_material(Arc::new(Diffuse::new(Color::splat(albedo))));
+        let blocker = Sphere::new((0.0, 1.0, 0.0), 0.5);
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(PointLight::new(
+            DVec3::new(0.0, height, 0.0),
+            Color::splat(intensity),
         ))];
-        let lights = vec![Sphere::new((4.0, 0.0, 2.0), 1.0)];
-        let octree = Octree::new(
-            &scene.iter().map(|obj_box| obj_box.as_ref()).collect(),
+        let ray = Ray::from_to((1.0, 1.0, 0.0), (0.0, 0.0, 0.0));
+
+        let lit: Vec<Box<dyn Intersect>> = vec![Box::new(floor.clone())];
+        let world = World::new(&lit).with_lights(&lights);
+        let expected = albedo / PI * intensity / (height * height);
+        assert!(trace_ray(ray, &world, 2).abs_diff_eq(Color::splat(expected), 1e-9));
+
+        let shadowed: Vec<Box<dyn Intersect>> = vec![Box::new(floor), Box::new(blocker)];
+        let world = World::new(&shadowed).with_lights(&lights);
+        assert_eq!(trace_ray(ray, &world, 2), Color::ZERO);
+    }
+
+    #[test]
+    fn environment_lights_the_scene() {
+        // a convex diffuse object under a uniform sky reflects albedo * sky
+        let (albedo, sky) = (0.5, 2.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![Box::new(
+            Sphere::new((0.0, 0.0, 0.0), 1.0)
+                .with_material(Arc::new(Diffuse::new(Color::splat(albedo)))),
+        )];
+        let lights: Vec<Box<dyn Light>> = vec![Box::new(EnvironmentLight::new(
             2,
             2,
-            AABBox {
-                min: DVec3::new(-10.0, -10.0, -10.0),
-                max: DVec3::new(10.0, 10.0, 10.0),
-            },
+            vec![Color::splat(sky); 4],
+        ))];
+        let world = World::new(&scene).with_lights(&lights);
+
+        let background = Ray::from_to((0.0, 0.0, 5.0), (5.0, 0.0, 0.0));
+        assert_eq!(trace_ray(background, &world, 2), Color::splat(sky));
+
+        let n = 10_000;
+        let ray = Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        let mean = (0..n)
+            .map(|_| trace_ray(ray, &world, 2))
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            / n as f64;
+
+        let expected = albedo * sky;
+        assert!(
+            mean.abs_diff_eq(Color::splat(expected), 0.01 * expected),
+            "{mean}"
         );
+    }
+
+    #[test]
+    fn smoke_keeps_the_light_of_a_white_furnace() {
+        // a medium that scatters without absorbing, under a uniform sky, sends back all of the
+        // light it gets, whatever the paths inside
+        let scene: Vec<Box<dyn Intersect>> =
+            vec![Box::new(Sphere::new((0.0, 0.0, 0.0), 1.0).with_material(
+                Arc::new(MediumBoundary::new(Medium::fog(Color::ONE, 2.0, 0.5))),
+            ))];
+        let lights: Vec<Box<dyn Light>> =
+            vec![Box::new(EnvironmentLight::new(1, 1, vec![Color::ONE]))];
+        let world = World::new(&scene).with_lights(&lights);
+
+        let n = 20_000;
+        let ray = Ray::from_to((0.0, 0.3, 5.0), (0.0, 0.0, 0.0));
+        let mean = (0..n)
+            .map(|_| trace_ray(ray, &world, usize::MAX))
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            / n as f64;
+
+        assert!(mean.abs_diff_eq(Color::ONE, 2e-2), "{mean}");
+    }
+
+    #[test]
+    fn absorbing_media_follow_beer_lambert() {
+        let sigma_a = Color::new(0.1, 0.2, 0.4);
+        let absorbing = Medium::new(sigma_a, Color::ZERO, 0.0);
+        let scene: Vec<Box<dyn Intersect>> = vec![
+            Box::new(
+                Sphere::new((0.0, 0.0, 0.0), 1.0)
+                    .with_material(Arc::new(MediumBoundary::new(absorbing))),
+            ),
+            Box::new(
+                Sphere::new((0.0, 0.0, -6.0), 1.0)
+                    .with_material(Arc::new(Emissive::new(Color::ONE))),
+            ),
+        ];
+        let world = World::new(&scene);
+
+        // through the diameter of the sphere to the light behind it
+        let ray = Ray::from_to((0.0, 0.0, 5.0), (0.0, 0.0, 0.0));
+        let expected = (-2.0 * sigma_a).exp();
+        assert!(trace_ray(ray, &world, 4).abs_diff_eq(expected, 1e-3));
 
-        debug!("{:?}", trace_ray(ray, &octree, &lights, 10));
+        // as fog, all the way to the light
+        let world = World::new(&scene).with_medium(Some(absorbing));
+        let ray = Ray::from_to((0.0, 2.0, 5.0), (0.0, 0.0, -6.0));
+        let distance = DVec3::new(0.0, 2.0, 5.0).distance(DVec3::new(0.0, 0.0, -6.0)) - 1.0;
+        let expected = (-distance * sigma_a).exp();
+        assert!(trace_ray(ray, &world, 4).abs_diff_eq(expected, 1e-3));
     }
 }